@@ -27,6 +27,7 @@ struct Cli {
 #[derive(Subcommand)]
 enum Commands {
     Start(Start),
+    Ctl(Ctl),
 }
 
 #[derive(Parser)]
@@ -36,18 +37,33 @@ struct Start {
     pub module: Modules,
 }
 
+#[derive(Parser)]
+#[command(about = "Control a module, do `pfui ctl --help` for list of available modules")]
+struct Ctl {
+    #[command(subcommand)]
+    pub module: CtlModules,
+}
+
 #[derive(Subcommand)]
 enum Modules {
-    Mpd,
+    Mpd(mpd::MpdOpts),
     #[command(name = "pulseaudio")]
-    PulseAudio,
+    PulseAudio(pulseaudio::PulseAudioOpts),
     #[command(alias = "i3")]
-    Sway,
+    Sway(sway::SwayOpts),
     #[command(subcommand)]
     Hyprland(hyprland::HyprlandOpts),
     Backlight,
 }
 
+#[derive(Subcommand)]
+enum CtlModules {
+    Mpd(mpd::MpdCtl),
+    #[command(alias = "i3")]
+    Sway(sway::SwayCtl),
+    Hyprland(hyprland::HyprlandCtl),
+}
+
 #[derive(Debug, Serialize)]
 struct Output<T: serde::Serialize> {
     ok: u8,
@@ -85,25 +101,27 @@ fn main() {
 
     match &cli.command {
         Some(Commands::Start(start)) => match start.module {
-            Modules::Mpd => {
+            Modules::Mpd(ref opts) => {
                 if cfg!(feature = "mpd") {
-                    while let Err(..) = (mpd::Mpd {}.start(5)) {}
+                    // Reconnects with the same module, so the song in progress isn't forgotten
+                    let mut mpd = mpd::Mpd::new(opts);
+                    while mpd.start(5).is_err() {}
                     exit(0);
                 } else {
                     println!("Feature not enabled");
                 }
             }
-            Modules::PulseAudio => {
-                if cfg!(feature = "pulseaudio") {
-                    while let Err(..) = (pulseaudio::PulseAudio {}.start(5)) {}
-                    exit(0);
+            Modules::PulseAudio(ref opts) => {
+                if let Some(reason) = opts.unsupported() {
+                    println!("{reason}");
                 } else {
-                    println!("Feature not enabled");
+                    while let Err(..) = (pulseaudio::PulseAudio::new(opts).start(5)) {}
+                    exit(0);
                 }
             }
-            Modules::Sway => {
+            Modules::Sway(ref opts) => {
                 if cfg!(feature = "sway") {
-                    while let Err(..) = (sway::Sway {}.start(5)) {}
+                    while let Err(..) = (sway::Sway::new(opts).start(5)) {}
                     exit(0);
                 } else {
                     println!("Feature not enabled");
@@ -125,6 +143,35 @@ fn main() {
                 }
             }
         },
+        Some(Commands::Ctl(ctl)) => {
+            let result = match ctl.module {
+                CtlModules::Mpd(ref ctl) => {
+                    if cfg!(feature = "mpd") {
+                        mpd::control(ctl)
+                    } else {
+                        Err(anyhow::anyhow!("Feature not enabled"))
+                    }
+                }
+                CtlModules::Sway(ref ctl) => {
+                    if cfg!(feature = "sway") {
+                        sway::control(ctl)
+                    } else {
+                        Err(anyhow::anyhow!("Feature not enabled"))
+                    }
+                }
+                CtlModules::Hyprland(ref ctl) => {
+                    if cfg!(feature = "hyprland") {
+                        hyprland::control(ctl)
+                    } else {
+                        Err(anyhow::anyhow!("Feature not enabled"))
+                    }
+                }
+            };
+            if let Err(e) = result {
+                eprintln!("{e}");
+                exit(1);
+            }
+        }
         None => {}
     }
 }
@@ -1,17 +1,107 @@
 use anyhow::Result;
-use clap::Subcommand;
+use clap::{Args, Subcommand};
 use hyprland::{
-    data::{Client, Devices, Workspace, Workspaces},
+    data::{Client, Clients, Devices, Workspace, Workspaces},
+    dispatch::{Dispatch, DispatchType, WindowIdentifier},
     event_listener::EventListener,
     prelude::*,
+    shared::Address,
 };
 use serde::Serialize;
+use std::{
+    collections::{BTreeMap, HashSet},
+    sync::{Arc, Mutex},
+};
 
 #[derive(Subcommand)]
 pub enum HyprlandOpts {
     Workspace,
     Window,
     Keyboard,
+    /// Every workspace with its windows
+    Taskbar,
+}
+
+#[derive(Args)]
+pub struct HyprlandCtl {
+    #[command(subcommand)]
+    command: HyprlandCommand,
+}
+
+#[derive(Subcommand)]
+enum HyprlandCommand {
+    /// Focus a window by its address, e.g. from the taskbar mode
+    Focus { address: String },
+}
+
+pub fn control(ctl: &HyprlandCtl) -> Result<()> {
+    match &ctl.command {
+        HyprlandCommand::Focus { address } => Dispatch::call(DispatchType::FocusWindow(
+            WindowIdentifier::Address(Address::new(address)),
+        ))?,
+    }
+    Ok(())
+}
+
+/// A workspace with its windows, for a taskbar
+#[derive(Serialize)]
+struct TaskbarWorkspace {
+    id: i32,
+    name: String,
+    windows: Vec<TaskbarWindow>,
+}
+
+#[derive(Serialize)]
+struct TaskbarWindow {
+    address: String,
+    class: String,
+    title: String,
+    focused: bool,
+    urgent: bool,
+    floating: bool,
+}
+
+/// The addresses of the windows that want attention, Hyprland only reports when a window becomes
+/// urgent, so this is cleared once a window is focused or closed
+type Urgent = Arc<Mutex<HashSet<String>>>;
+
+fn print_taskbar(urgent: &Urgent) {
+    let Ok(clients) = Clients::get() else {
+        return crate::print::<()>(&None);
+    };
+    let clients: Vec<Client> = clients.into_iter().collect();
+    let active = Client::get_active()
+        .ok()
+        .flatten()
+        .map(|client| client.address.to_string());
+    let mut urgent = urgent.lock().unwrap();
+    urgent.retain(|address| {
+        active.as_ref() != Some(address)
+            && clients
+                .iter()
+                .any(|client| &client.address.to_string() == address)
+    });
+    let mut workspaces: BTreeMap<i32, TaskbarWorkspace> = BTreeMap::new();
+    for client in clients {
+        let address = client.address.to_string();
+        workspaces
+            .entry(client.workspace.id)
+            .or_insert_with(|| TaskbarWorkspace {
+                id: client.workspace.id,
+                name: client.workspace.name.clone(),
+                windows: Vec::new(),
+            })
+            .windows
+            .push(TaskbarWindow {
+                focused: active.as_ref() == Some(&address),
+                urgent: urgent.contains(&address),
+                address,
+                class: client.class,
+                title: client.title,
+                floating: client.floating,
+            });
+    }
+    crate::print(&Some(workspaces.into_values().collect::<Vec<_>>()));
 }
 
 #[derive(Serialize)]
@@ -117,6 +207,26 @@ impl HyprlandListener {
                     print_keyboard();
                 })
             }
+            HyprlandOpts::Taskbar => {
+                let urgent = Urgent::default();
+                print_taskbar(&urgent);
+                let u = urgent.clone();
+                listener.add_window_open_handler(move |_| print_taskbar(&u));
+                let u = urgent.clone();
+                listener.add_window_close_handler(move |_| print_taskbar(&u));
+                let u = urgent.clone();
+                listener.add_window_moved_handler(move |_| print_taskbar(&u));
+                let u = urgent.clone();
+                listener.add_active_window_change_handler(move |_| print_taskbar(&u));
+                let u = urgent.clone();
+                listener.add_float_state_handler(move |_| print_taskbar(&u));
+                let u = urgent.clone();
+                listener.add_window_title_change_handler(move |_| print_taskbar(&u));
+                listener.add_urgent_state_handler(move |address| {
+                    urgent.lock().unwrap().insert(address.to_string());
+                    print_taskbar(&urgent);
+                });
+            }
         };
         Self { listener }
     }
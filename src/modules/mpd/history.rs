@@ -0,0 +1,195 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::PathBuf,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+use super::{Song, State};
+
+/// One line of the history file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Play {
+    /// When the song started playing, as a unix timestamp
+    timestamp: i64,
+    file_path: String,
+    title: Option<String>,
+    artist: Option<String>,
+    album: Option<String>,
+    duration: Option<i64>,
+    /// How many seconds of the song were actually listened to
+    played: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Summary {
+    plays_today: usize,
+    last_played: Option<Play>,
+}
+
+struct Current {
+    play: Play,
+    id: Option<u32>,
+    /// The elapsed time of the last update, going back to the start means the song was restarted
+    elapsed: Option<i64>,
+    listened: Duration,
+    /// Set while the song is playing, so the time since then can be added to `listened`
+    since: Option<Instant>,
+}
+
+/// Keeps track of what's being listened to and appends finished plays to a jsonl file
+pub struct History {
+    path: PathBuf,
+    timestamps: Vec<i64>,
+    last: Option<Play>,
+    current: Option<Current>,
+}
+
+/// How close to the start of the song playback has to go back to, to count as playing it again
+/// instead of seeking back a bit
+const RESTART_SECONDS: i64 = 2;
+
+fn unix_now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|now| now.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+impl History {
+    pub fn open(path: PathBuf) -> Self {
+        let plays: Vec<Play> = File::open(&path)
+            .map(|file| {
+                BufReader::new(file)
+                    .lines()
+                    .map_while(Result::ok)
+                    .filter_map(|line| serde_json::from_str(&line).ok())
+                    .collect()
+            })
+            .unwrap_or_default();
+        Self {
+            path,
+            timestamps: plays.iter().map(|play| play.timestamp).collect(),
+            last: plays.last().cloned(),
+            current: None,
+        }
+    }
+
+    /// Has to be called on every update with the current song, its state and whether it's playing
+    pub fn update(&mut self, song: &Song, state: &State, playing: bool) {
+        let now = Instant::now();
+        if let Some(current) = &mut self.current {
+            if let Some(since) = current.since.take() {
+                current.listened += now - since;
+            }
+        }
+        let same_song = matches!(
+            (&self.current, &song.file_path),
+            (Some(current), Some(file_path)) if &current.play.file_path == file_path
+                && current.id == song.id
+                && !matches!(
+                    (current.elapsed, state.elapsed),
+                    (Some(previous), Some(elapsed)) if elapsed < previous && elapsed <= RESTART_SECONDS
+                )
+        );
+        if !same_song {
+            if let Some(finished) = self.current.take() {
+                self.finish(finished);
+            }
+            self.current = song.file_path.as_ref().map(|file_path| Current {
+                play: Play {
+                    timestamp: unix_now(),
+                    file_path: file_path.clone(),
+                    title: song.title.clone(),
+                    artist: song.artist.clone(),
+                    album: song.album.clone(),
+                    duration: state.duration,
+                    played: 0,
+                },
+                id: song.id,
+                elapsed: None,
+                listened: Duration::ZERO,
+                since: None,
+            });
+        }
+        if let Some(current) = &mut self.current {
+            current.elapsed = state.elapsed;
+            current.since = playing.then_some(now);
+        }
+    }
+
+    /// Stops counting time as listened until the next update, for when the connection to mpd is
+    /// lost and it's unknown whether anything is playing
+    pub fn interrupt(&mut self) {
+        if let Some(current) = &mut self.current {
+            if let Some(since) = current.since.take() {
+                current.listened += since.elapsed();
+            }
+        }
+    }
+
+    /// Records the song in progress, for when pfui exits
+    pub fn stop(&mut self) {
+        self.interrupt();
+        if let Some(current) = self.current.take() {
+            self.finish(current);
+        }
+    }
+
+    /// Records the play if it counts by the usual scrobbling rules: the song is longer than 30
+    /// seconds and was played for at least half its duration or for 4 minutes
+    fn finish(&mut self, current: Current) {
+        let listened = current.listened.as_secs();
+        let required = match current.play.duration {
+            Some(duration) if duration < 30 => return,
+            Some(duration) => (duration as u64 / 2).min(240),
+            None => 240,
+        };
+        if listened < required {
+            return;
+        }
+        let play = Play {
+            played: listened,
+            ..current.play
+        };
+        if let Err(e) = self.append(&play) {
+            eprintln!("Failed to write to history file {:?}: {e}", self.path);
+        }
+        self.timestamps.push(play.timestamp);
+        self.last = Some(play);
+    }
+
+    fn append(&self, play: &Play) -> anyhow::Result<()> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        writeln!(file, "{}", serde_json::to_string(play)?)?;
+        Ok(())
+    }
+
+    pub fn summary(&self) -> Summary {
+        let mut midnight = time::now();
+        midnight.tm_hour = 0;
+        midnight.tm_min = 0;
+        midnight.tm_sec = 0;
+        midnight.tm_nsec = 0;
+        let midnight = midnight.to_timespec().sec;
+        Summary {
+            plays_today: self
+                .timestamps
+                .iter()
+                .filter(|timestamp| **timestamp >= midnight)
+                .count(),
+            last_played: self.last.clone(),
+        }
+    }
+}
+
+impl Drop for History {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
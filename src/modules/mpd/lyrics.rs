@@ -0,0 +1,133 @@
+use std::path::{Path, PathBuf};
+
+/// Synchronized lyrics read from an .lrc file
+pub struct Lyrics {
+    /// The lines of the song, sorted by the time in seconds at which they start
+    lines: Vec<(f64, String)>,
+}
+
+/// Parses an lrc timestamp like `01:23.45` into seconds
+fn parse_timestamp(tag: &str) -> Option<f64> {
+    let (minutes, seconds) = tag.split_once(':')?;
+    let minutes = minutes.trim().parse::<u32>().ok()?;
+    let seconds = seconds.trim().parse::<f64>().ok()?;
+    Some(f64::from(minutes) * 60.0 + seconds)
+}
+
+impl Lyrics {
+    pub fn parse(content: &str) -> Self {
+        let mut offset = 0.0;
+        let mut lines = Vec::new();
+        for line in content.lines() {
+            let mut rest = line.trim();
+            let mut times = Vec::new();
+            // A line can start with multiple timestamps if it's repeated throughout the song
+            while rest.starts_with('[') {
+                let Some(end) = rest.find(']') else {
+                    break;
+                };
+                let tag = &rest[1..end];
+                rest = &rest[end + 1..];
+                if let Some(time) = parse_timestamp(tag) {
+                    times.push(time);
+                } else if let Some(value) = tag.strip_prefix("offset:") {
+                    // The offset is in milliseconds, a positive value makes lines appear sooner
+                    offset = value.trim().parse::<f64>().unwrap_or(0.0) / 1000.0;
+                }
+            }
+            for time in times {
+                lines.push((time, rest.trim().to_owned()));
+            }
+        }
+        for line in &mut lines {
+            line.0 -= offset;
+        }
+        lines.sort_by(|a, b| a.0.total_cmp(&b.0));
+        Self { lines }
+    }
+
+    pub fn read(path: &Path) -> Option<Self> {
+        std::fs::read_to_string(path)
+            .ok()
+            .map(|content| Self::parse(&content))
+    }
+
+    /// Returns the current line, the next line and in how many seconds the next line starts
+    pub fn at(&self, elapsed: f64) -> (Option<&str>, Option<&str>, Option<f64>) {
+        let position = self.lines.partition_point(|(time, _)| *time <= elapsed);
+        let current = position
+            .checked_sub(1)
+            .map(|index| self.lines[index].1.as_str());
+        let next = self.lines.get(position);
+        (
+            current,
+            next.map(|(_, line)| line.as_str()),
+            next.map(|(time, _)| time - elapsed),
+        )
+    }
+}
+
+/// Looks for an .lrc file next to the song in the music directory, or in the lyrics directory
+/// either under the same path as the song or just by its file name
+pub fn find(
+    file_path: &str,
+    music_directory: Option<&Path>,
+    lyrics_directory: Option<&Path>,
+) -> Option<PathBuf> {
+    let relative = Path::new(file_path).with_extension("lrc");
+    let mut candidates = Vec::new();
+    if let Some(directory) = music_directory {
+        candidates.push(directory.join(&relative));
+    }
+    if let Some(directory) = lyrics_directory {
+        candidates.push(directory.join(&relative));
+        if let Some(name) = relative.file_name() {
+            candidates.push(directory.join(name));
+        }
+    }
+    candidates.into_iter().find(|candidate| candidate.is_file())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Lyrics;
+
+    #[test]
+    fn repeated_lines() {
+        let lyrics = Lyrics::parse("[00:01.00][00:05.50]Chorus\n[00:03.00]Verse\n");
+        assert_eq!(
+            lyrics.lines,
+            vec![
+                (1.0, "Chorus".to_owned()),
+                (3.0, "Verse".to_owned()),
+                (5.5, "Chorus".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn offset() {
+        let lyrics = Lyrics::parse("[offset:+500]\n[00:02.00]First\n[01:00.00]Second\n");
+        assert_eq!(
+            lyrics.lines,
+            vec![(1.5, "First".to_owned()), (59.5, "Second".to_owned())]
+        );
+        let lyrics = Lyrics::parse("[offset:-250]\n[00:02.00]First\n");
+        assert_eq!(lyrics.lines, vec![(2.25, "First".to_owned())]);
+    }
+
+    #[test]
+    fn metadata_tags() {
+        let lyrics =
+            Lyrics::parse("[ti:Title]\n[ar:Artist]\n[al:Album]\n[length: 03:20]\n[00:10.00]Line\n");
+        assert_eq!(lyrics.lines, vec![(10.0, "Line".to_owned())]);
+    }
+
+    #[test]
+    fn at() {
+        let lyrics = Lyrics::parse("[00:01.00]First\n[00:03.00]Second\n");
+        assert_eq!(lyrics.at(0.5), (None, Some("First"), Some(0.5)));
+        assert_eq!(lyrics.at(2.0), (Some("First"), Some("Second"), Some(1.0)));
+        assert_eq!(lyrics.at(4.0), (Some("Second"), None, None));
+    }
+}
@@ -0,0 +1,714 @@
+use anyhow::{anyhow, Result};
+use std::{
+    cell::RefCell,
+    collections::BTreeMap,
+    io::{BufRead, BufReader, ErrorKind, Write},
+    net::TcpStream,
+    path::PathBuf,
+    process::exit,
+    sync::{Arc, Mutex},
+    thread::{self, sleep},
+    time::{Duration, Instant},
+};
+
+use clap::{Args, Subcommand, ValueEnum};
+use mpd::{idle::Subsystem, Client, Idle, Song as MpdSong, State as OldMpdState, Status};
+use nix::sys::signal::{SigSet, Signal};
+use serde::{Serialize, Serializer};
+
+use crate::Module;
+
+mod history;
+mod lyrics;
+
+const ADDRESS: &str = "127.0.0.1:6600";
+
+#[derive(Args, Clone)]
+pub struct MpdOpts {
+    /// The mpd partition to follow, defaults to the partition new clients start in
+    #[arg(long)]
+    partition: Option<String>,
+    /// The music directory configured in mpd, used to find .lrc files next to songs
+    #[arg(long)]
+    music_directory: Option<PathBuf>,
+    /// A directory containing .lrc files, either by song path or just by file name
+    #[arg(long)]
+    lyrics_directory: Option<PathBuf>,
+    /// Append songs that were listened to to this file, one json object per line
+    #[arg(long)]
+    history_file: Option<PathBuf>,
+}
+
+#[derive(Args)]
+pub struct MpdCtl {
+    /// The mpd partition to send the command to
+    #[arg(long)]
+    partition: Option<String>,
+    #[command(subcommand)]
+    command: MpdCommand,
+}
+
+#[derive(Subcommand)]
+enum MpdCommand {
+    /// Enable, disable or toggle an audio output
+    Output {
+        action: OutputAction,
+        /// The id or name of the output
+        output: String,
+    },
+    /// Set the rating sticker of the current song
+    Rate {
+        /// The rating from 0 to 10, the scale most clients use for half stars
+        #[arg(value_parser = clap::value_parser!(u8).range(0..=10))]
+        rating: u8,
+    },
+    /// Set or delete a sticker on the current song
+    Sticker {
+        #[command(subcommand)]
+        action: StickerAction,
+    },
+}
+
+#[derive(Subcommand)]
+enum StickerAction {
+    Set { name: String, value: String },
+    Delete { name: String },
+}
+
+#[derive(Clone, ValueEnum)]
+enum OutputAction {
+    Enable,
+    Disable,
+    Toggle,
+}
+
+#[derive(Debug, Serialize)]
+struct Data {
+    song: Song,
+    state: State,
+    options: Options,
+    audio: Option<Audio>,
+    partition: Option<String>,
+    outputs: Vec<Output>,
+    database: Option<Database>,
+    lyrics: Option<LyricsData>,
+    stickers: BTreeMap<String, String>,
+    history: Option<history::Summary>,
+}
+#[derive(Debug, Serialize)]
+struct Song {
+    /// The id of the song in the queue, which changes when it's played again from the queue
+    #[serde(skip)]
+    id: Option<u32>,
+    file_path: Option<String>,
+    title: Option<String>,
+    album: Option<String>,
+    artist: Option<String>,
+    date: Option<String>,
+    genre: Option<String>,
+}
+impl Song {
+    fn empty() -> Self {
+        Song {
+            id: None,
+            file_path: None,
+            title: None,
+            album: None,
+            artist: None,
+            date: None,
+            genre: None,
+        }
+    }
+}
+#[derive(Debug, Serialize)]
+struct State {
+    elapsed: Option<i64>,
+    duration: Option<i64>,
+    remaining: Option<i64>,
+    elapsed_str: Option<String>,
+    duration_str: Option<String>,
+    remaining_str: Option<String>,
+    progress: Option<i8>,
+    status: Option<MpdState>,
+    status_name: Option<&'static str>,
+}
+#[derive(Debug, Serialize)]
+struct Options {
+    volume: i8,
+    repeat: bool,
+    random: bool,
+    consume: bool,
+    single: Single,
+    crossfade: Option<i64>,
+    mixrampdb: Option<f32>,
+    replay_gain: Option<String>,
+}
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum Single {
+    Off,
+    On,
+    Oneshot,
+}
+#[derive(Debug, Serialize)]
+struct Output {
+    id: u32,
+    name: String,
+    enabled: bool,
+}
+#[derive(Debug, Serialize)]
+struct Database {
+    updating: bool,
+    update_job: Option<u32>,
+    artists: Option<u64>,
+    albums: Option<u64>,
+    songs: Option<u64>,
+    db_playtime: Option<u64>,
+    db_update: Option<u64>,
+    uptime: Option<u64>,
+    playtime: Option<u64>,
+}
+#[derive(Debug, Serialize)]
+struct LyricsData {
+    current: Option<String>,
+    next: Option<String>,
+    /// In how many seconds the next line starts, used to wake up in time for it
+    #[serde(skip)]
+    next_in: Option<f64>,
+}
+#[derive(Debug, Serialize)]
+struct Audio {
+    sample_rate: u32,
+    bits: u8,
+    channels: u8,
+    bitrate: Option<u32>,
+}
+
+#[derive(Debug)]
+struct MpdState(OldMpdState);
+impl Serialize for MpdState {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        if self.0 == OldMpdState::Play {
+            return serializer.serialize_i8(0);
+        } else if self.0 == OldMpdState::Pause {
+            return serializer.serialize_i8(1);
+        } else if self.0 == OldMpdState::Stop {
+            return serializer.serialize_i8(2);
+        }
+        Err(serde::ser::Error::custom("Error serializing MpdState"))
+    }
+}
+impl MpdState {
+    fn name(&self) -> &'static str {
+        match self.0 {
+            OldMpdState::Play => "play",
+            OldMpdState::Pause => "pause",
+            OldMpdState::Stop => "stop",
+        }
+    }
+}
+
+/// Formats a number of seconds like `3:07`, or `1:02:44` if it's longer than an hour
+fn format_duration(seconds: i64) -> String {
+    let (hours, minutes, seconds) = (seconds / 3600, seconds % 3600 / 60, seconds % 60);
+    if hours > 0 {
+        format!("{hours}:{minutes:02}:{seconds:02}")
+    } else {
+        format!("{minutes}:{seconds:02}")
+    }
+}
+
+impl From<&MpdSong> for Song {
+    fn from(value: &MpdSong) -> Self {
+        Song {
+            id: value.place.map(|place| place.id.0),
+            file_path: Some(value.file.clone()),
+            title: value.title.clone(),
+            album: value.tags.get("Album").cloned(),
+            artist: value.tags.get("Artist").cloned(),
+            date: value.tags.get("Date").cloned(),
+            genre: value.tags.get("Genre").cloned(),
+        }
+    }
+}
+
+impl From<&Status> for State {
+    fn from(value: &Status) -> Self {
+        let elapsed = value.elapsed.map(|elapsed| elapsed.num_seconds());
+        let duration = value.duration.map(|duration| duration.num_seconds());
+        let progress = if let (Some(elapsed), Some(duration)) = (elapsed, duration) {
+            if let (Ok(elapsed), Ok(duration)) = (i32::try_from(elapsed), i32::try_from(duration)) {
+                i8::try_from(((f64::from(elapsed) / f64::from(duration)) * 100.0).round() as i64)
+                    .ok()
+            } else {
+                None
+            }
+        } else {
+            None
+        };
+        let remaining = if let (Some(elapsed), Some(duration)) = (elapsed, duration) {
+            Some((duration - elapsed).max(0))
+        } else {
+            None
+        };
+        let status = MpdState(value.state);
+        State {
+            elapsed,
+            duration,
+            remaining,
+            elapsed_str: elapsed.map(format_duration),
+            duration_str: duration.map(format_duration),
+            remaining_str: remaining.map(format_duration),
+            progress,
+            status_name: Some(status.name()),
+            status: Some(status),
+        }
+    }
+}
+
+impl From<&Status> for Options {
+    fn from(value: &Status) -> Self {
+        Options {
+            volume: value.volume,
+            repeat: value.repeat,
+            random: value.random,
+            consume: value.consume,
+            single: if value.single {
+                Single::On
+            } else {
+                Single::Off
+            },
+            crossfade: value.crossfade.map(|crossfade| crossfade.num_seconds()),
+            mixrampdb: None,
+            replay_gain: None,
+        }
+    }
+}
+
+impl Audio {
+    fn from_status(value: &Status) -> Option<Self> {
+        value.audio.as_ref().map(|audio| Audio {
+            sample_rate: audio.rate,
+            bits: audio.bits,
+            channels: audio.chans,
+            bitrate: value.bitrate,
+        })
+    }
+}
+
+impl
+    TryFrom<(
+        Result<std::option::Option<MpdSong>, mpd::error::Error>,
+        Result<Status, mpd::error::Error>,
+    )> for Data
+{
+    type Error = mpd::error::Error;
+    fn try_from(
+        value: (
+            Result<std::option::Option<MpdSong>, mpd::error::Error>,
+            Result<Status, mpd::error::Error>,
+        ),
+    ) -> Result<Self, Self::Error> {
+        let status = value.1?;
+        if let Ok(Some(current_song)) = value.0 {
+            Ok(Data {
+                song: Song::from(&current_song),
+                state: State::from(&status),
+                options: Options::from(&status),
+                audio: Audio::from_status(&status),
+                partition: None,
+                outputs: Vec::new(),
+                database: None,
+                lyrics: None,
+                stickers: BTreeMap::new(),
+                history: None,
+            })
+        } else {
+            Ok(Data {
+                song: Song::empty(),
+                state: State::from(&status),
+                options: Options::from(&status),
+                audio: Audio::from_status(&status),
+                partition: None,
+                outputs: Vec::new(),
+                database: None,
+                lyrics: None,
+                stickers: BTreeMap::new(),
+                history: None,
+            })
+        }
+    }
+}
+
+/// Sends commands over the same socket the client uses, for the parts of the protocol the mpd
+/// crate doesn't cover. Only call this while the client isn't waiting for a response.
+pub struct Raw {
+    reader: BufReader<TcpStream>,
+}
+
+impl Raw {
+    fn command(&mut self, command: &str) -> Result<Vec<(String, String)>> {
+        self.reader
+            .get_mut()
+            .write_all(format!("{command}\n").as_bytes())?;
+        let mut pairs = Vec::new();
+        loop {
+            let mut line = String::new();
+            if self.reader.read_line(&mut line)? == 0 {
+                return Err(anyhow!("Connection to mpd closed"));
+            }
+            let line = line.trim_end_matches('\n');
+            if line == "OK" {
+                return Ok(pairs);
+            }
+            if let Some(error) = line.strip_prefix("ACK ") {
+                return Err(anyhow!("mpd error: {error}"));
+            }
+            if let Some((key, value)) = line.split_once(": ") {
+                pairs.push((key.to_owned(), value.to_owned()));
+            }
+        }
+    }
+
+    /// Sets how long reads on the connection may block, this is shared with the client
+    fn set_timeout(&self, timeout: Option<Duration>) -> Result<()> {
+        Ok(self.reader.get_ref().set_read_timeout(timeout)?)
+    }
+
+    fn field(pairs: &[(String, String)], key: &str) -> Option<String> {
+        pairs
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, value)| value.clone())
+    }
+}
+
+/// Quotes an argument so it can be sent as part of an mpd command
+fn quote(argument: &str) -> String {
+    format!(
+        "\"{}\"",
+        argument.replace('\\', "\\\\").replace('"', "\\\"")
+    )
+}
+
+pub struct Connection {
+    client: Client,
+    raw: Raw,
+}
+
+impl Connection {
+    fn new(address: &str, partition: Option<&str>) -> Result<Self> {
+        let stream = TcpStream::connect(address)?;
+        let mut raw = Raw {
+            reader: BufReader::new(stream.try_clone()?),
+        };
+        let client = Client::new(stream)?;
+        if let Some(partition) = partition {
+            match raw.command(&format!("partition {}", quote(partition))) {
+                // Anything but an io error is mpd refusing the partition, retrying won't help
+                Err(error) if error.downcast_ref::<std::io::Error>().is_none() => {
+                    eprintln!("Failed to switch to partition {partition:?}: {error}");
+                    exit(1);
+                }
+                result => result?,
+            };
+        }
+        Ok(Self { client, raw })
+    }
+}
+
+fn get_outputs(client: &mut Client) -> Result<Vec<Output>> {
+    Ok(client
+        .outputs()?
+        .into_iter()
+        .map(|output| Output {
+            id: output.id,
+            name: output.name,
+            enabled: output.enabled,
+        })
+        .collect())
+}
+
+fn get_database(client: &mut Client, update_job: Option<u32>) -> Result<Database> {
+    let stats = client.stats()?;
+    let seconds = |duration: time::Duration| u64::try_from(duration.num_seconds()).ok();
+    Ok(Database {
+        updating: update_job.is_some(),
+        update_job,
+        artists: Some(stats.artists.into()),
+        albums: Some(stats.albums.into()),
+        songs: Some(stats.songs.into()),
+        db_playtime: seconds(stats.db_playtime),
+        db_update: u64::try_from(stats.db_update.sec).ok(),
+        uptime: seconds(stats.uptime),
+        playtime: seconds(stats.playtime),
+    })
+}
+
+/// `Client::stickers` only returns the values of the stickers without their names, so they're
+/// listed with the raw command
+fn get_stickers(raw: &mut Raw, file_path: &str) -> Result<BTreeMap<String, String>> {
+    Ok(raw
+        .command(&format!("sticker list song {}", quote(file_path)))?
+        .into_iter()
+        .filter(|(key, _)| key == "sticker")
+        .filter_map(|(_, sticker)| {
+            let (name, value) = sticker.split_once('=')?;
+            Some((name.to_owned(), value.to_owned()))
+        })
+        .collect())
+}
+
+/// The lyrics of the last song, so they're only looked up and parsed again once the song changes
+type LyricsCache = Option<(String, Option<lyrics::Lyrics>)>;
+
+fn get_lyrics(
+    opts: &MpdOpts,
+    cache: &mut LyricsCache,
+    file_path: &str,
+    elapsed: f64,
+) -> Option<LyricsData> {
+    if !matches!(cache, Some((cached, _)) if cached == file_path) {
+        let lyrics = lyrics::find(
+            file_path,
+            opts.music_directory.as_deref(),
+            opts.lyrics_directory.as_deref(),
+        )
+        .and_then(|path| lyrics::Lyrics::read(&path));
+        *cache = Some((file_path.to_owned(), lyrics));
+    }
+    let (_, lyrics) = cache.as_ref()?;
+    let (current, next, next_in) = lyrics.as_ref()?.at(elapsed);
+    Some(LyricsData {
+        current: current.map(str::to_owned),
+        next: next.map(str::to_owned),
+        next_in,
+    })
+}
+
+/// Returns the data along with the exact number of seconds elapsed, which lyrics are timed by
+fn get_info(
+    conn: &mut Connection,
+    opts: &MpdOpts,
+    lyrics: &mut LyricsCache,
+) -> Option<(Data, f64)> {
+    let current_song = conn.client.currentsong();
+    let status = conn.client.status().ok()?;
+    let update_job = status.updating_db;
+    let elapsed = status
+        .elapsed
+        .map_or(0.0, |elapsed| elapsed.num_milliseconds() as f64 / 1000.0);
+    let mut data = Data::try_from((current_song, Ok(status))).ok()?;
+    // The mpd crate only knows single as a boolean and never parses mixrampdb or the partition,
+    // so these are read from the raw status
+    if let Ok(status) = conn.raw.command("status") {
+        if Raw::field(&status, "single").as_deref() == Some("oneshot") {
+            data.options.single = Single::Oneshot;
+        }
+        data.options.mixrampdb = Raw::field(&status, "mixrampdb").and_then(|db| db.parse().ok());
+        data.partition = Raw::field(&status, "partition");
+    }
+    data.database = get_database(&mut conn.client, update_job).ok();
+    if let Some(file_path) = &data.song.file_path {
+        data.lyrics = get_lyrics(opts, lyrics, file_path, elapsed);
+    }
+    if let Ok(replay_gain) = conn.raw.command("replay_gain_status") {
+        data.options.replay_gain = Raw::field(&replay_gain, "replay_gain_mode");
+    }
+    data.outputs = get_outputs(&mut conn.client).unwrap_or_default();
+    if let Some(file_path) = &data.song.file_path {
+        // Songs without any stickers can result in an error depending on the mpd version
+        data.stickers = get_stickers(&mut conn.raw, file_path).unwrap_or_default();
+    }
+    Some((data, elapsed))
+}
+
+/// Sets a sticker on the current song, or deletes it if there's no value
+fn set_sticker(conn: &mut Connection, name: &str, value: Option<&str>) -> Result<()> {
+    let song = conn
+        .client
+        .currentsong()?
+        .ok_or_else(|| anyhow!("No song is playing"))?;
+    match value {
+        Some(value) => conn.client.set_sticker("song", &song.file, name, value)?,
+        None => conn.client.delete_sticker("song", &song.file, name)?,
+    }
+    Ok(())
+}
+
+/// Runs a single control command, for `pfui ctl mpd`
+pub fn control(ctl: &MpdCtl) -> Result<()> {
+    let mut conn = Connection::new(ADDRESS, ctl.partition.as_deref())?;
+    match &ctl.command {
+        MpdCommand::Output { action, output } => {
+            let id = match output.parse::<u32>() {
+                Ok(id) => id,
+                Err(_) => get_outputs(&mut conn.client)?
+                    .into_iter()
+                    .find(|o| &o.name == output)
+                    .map(|o| o.id)
+                    .ok_or_else(|| anyhow!("No output named {output}"))?,
+            };
+            match action {
+                OutputAction::Enable => conn.client.out_enable(id)?,
+                OutputAction::Disable => conn.client.out_disable(id)?,
+                OutputAction::Toggle => conn.client.out_toggle(id)?,
+            }
+        }
+        MpdCommand::Rate { rating } => {
+            set_sticker(&mut conn, "rating", Some(rating.to_string().as_str()))?;
+        }
+        MpdCommand::Sticker { action } => match action {
+            StickerAction::Set { name, value } => {
+                set_sticker(&mut conn, name, Some(value.as_str()))?
+            }
+            StickerAction::Delete { name } => set_sticker(&mut conn, name, None)?,
+        },
+    }
+    Ok(())
+}
+
+pub struct Mpd {
+    opts: MpdOpts,
+    history: Option<Arc<Mutex<history::History>>>,
+    lyrics: RefCell<LyricsCache>,
+    /// The last printed data with the seconds elapsed at the time it was read, so lyrics can
+    /// move on without asking mpd for everything again
+    last: RefCell<Option<(Data, f64, Instant)>>,
+}
+
+/// Adds the song in progress to the history when pfui is terminated, signals are blocked and
+/// waited for on a separate thread since the main one spends its time blocked in a read
+fn record_on_exit(history: Arc<Mutex<history::History>>) {
+    let mut signals = SigSet::empty();
+    signals.add(Signal::SIGINT);
+    signals.add(Signal::SIGTERM);
+    signals.add(Signal::SIGHUP);
+    if signals.thread_block().is_err() {
+        return;
+    }
+    thread::spawn(move || {
+        let signal = signals.wait();
+        if let Ok(mut history) = history.lock() {
+            history.stop();
+        }
+        exit(signal.map_or(1, |signal| 128 + signal as i32));
+    });
+}
+
+fn is_playing(data: &Data) -> bool {
+    matches!(data.state.status, Some(MpdState(OldMpdState::Play)))
+}
+
+impl Mpd {
+    pub fn new(opts: &MpdOpts) -> Self {
+        Self {
+            opts: opts.clone(),
+            history: opts.history_file.clone().map(|path| {
+                let history = Arc::new(Mutex::new(history::History::open(path)));
+                record_on_exit(Arc::clone(&history));
+                history
+            }),
+            lyrics: RefCell::new(None),
+            last: RefCell::new(None),
+        }
+    }
+
+    /// Prints the current data and returns when it should be printed again even if nothing
+    /// happens, which is the case while lyrics are being followed
+    fn update(&self, conn: &mut Connection) -> Option<Duration> {
+        let read_at = Instant::now();
+        let mut info = get_info(conn, &self.opts, &mut self.lyrics.borrow_mut());
+        if let (Some((info, _)), Some(history)) = (&mut info, &self.history) {
+            let mut history = history.lock().unwrap();
+            history.update(&info.song, &info.state, is_playing(info));
+            info.history = Some(history.summary());
+        }
+        crate::print(&info.as_ref().map(|(data, _)| data));
+        *self.last.borrow_mut() = info.map(|(data, elapsed)| (data, elapsed, read_at));
+        self.next_wakeup()
+    }
+
+    /// Moves the lyrics of the last data on to the current line and prints it again
+    fn update_lyrics(&self) -> Option<Duration> {
+        {
+            let mut last = self.last.borrow_mut();
+            if let Some((data, elapsed, read_at)) = last.as_mut() {
+                if let Some(file_path) = &data.song.file_path {
+                    let elapsed = *elapsed + read_at.elapsed().as_secs_f64();
+                    data.lyrics = get_lyrics(
+                        &self.opts,
+                        &mut self.lyrics.borrow_mut(),
+                        file_path,
+                        elapsed,
+                    );
+                }
+            }
+            crate::print(&last.as_ref().map(|(data, ..)| data));
+        }
+        self.next_wakeup()
+    }
+
+    fn next_wakeup(&self) -> Option<Duration> {
+        let last = self.last.borrow();
+        let (data, ..) = last.as_ref().filter(|(data, ..)| is_playing(data))?;
+        let next_in = data.lyrics.as_ref()?.next_in?;
+        Some(Duration::from_secs_f64(next_in.max(0.01)))
+    }
+}
+
+impl Module for Mpd {
+    type Connection = Connection;
+    fn connect(&mut self, timeout: u64) -> Result<Self::Connection> {
+        if let Some(history) = &self.history {
+            history.lock().unwrap().interrupt();
+        }
+        let partition = self.opts.partition.as_deref();
+        let mut conn_ = Connection::new(ADDRESS, partition);
+        while let Err(..) = conn_ {
+            conn_ = Connection::new(ADDRESS, partition);
+            crate::print(&None::<Data>);
+            sleep(Duration::new(timeout, 0));
+        }
+        conn_
+    }
+    fn output(&self, conn: &mut Self::Connection) {
+        self.update(conn);
+    }
+    fn start(&mut self, timeout: u64) -> Result<()> {
+        let mut conn = self.connect(timeout)?;
+        let mut wakeup = self.update(&mut conn);
+        loop {
+            conn.raw.set_timeout(wakeup)?;
+            let guard = conn.client.idle(&[
+                Subsystem::Player,
+                Subsystem::Mixer,
+                Subsystem::Options,
+                Subsystem::Output,
+                Subsystem::Update,
+                Subsystem::Database,
+                Subsystem::Sticker,
+            ])?;
+            match guard.get() {
+                Ok(_) => {}
+                // Nothing happened before the next lyrics line, so leave idle mode and only move
+                // the lyrics on, unless something changed right before noidle was sent
+                Err(mpd::error::Error::Io(e))
+                    if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) =>
+                {
+                    conn.raw.set_timeout(None)?;
+                    if conn.raw.command("noidle")?.is_empty() {
+                        wakeup = self.update_lyrics();
+                        continue;
+                    }
+                }
+                Err(_) => continue,
+            }
+            conn.raw.set_timeout(None)?;
+            wakeup = self.update(&mut conn);
+        }
+    }
+}
@@ -0,0 +1,118 @@
+use std::{collections::BTreeMap, rc::Rc, sync::Mutex};
+
+use pulse::{
+    context::{
+        introspect::{CardInfo, CardProfileInfo, Introspector},
+        subscribe::{Facility, InterestMaskSet, Operation},
+    },
+    proplist::properties,
+};
+use serde::Serialize;
+
+use super::{listen_listing, Listing, Port, Shared, SubscribeCallback};
+
+/// A configuration of a card, e.g. "a2dp-sink" or "headset-head-unit" for bluetooth headsets
+#[derive(Debug, Serialize)]
+struct Profile {
+    name: Option<String>,
+    description: Option<String>,
+    available: bool,
+    priority: u32,
+    sinks: u32,
+    sources: u32,
+}
+
+impl From<&CardProfileInfo<'_>> for Profile {
+    fn from(value: &CardProfileInfo) -> Self {
+        Profile {
+            name: value.name.as_ref().map(|name| name.to_string()),
+            description: value.description.as_ref().map(|d| d.to_string()),
+            available: value.available,
+            priority: value.priority,
+            sinks: value.n_sinks,
+            sources: value.n_sources,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct Card {
+    index: u32,
+    name: Option<String>,
+    description: Option<String>,
+    /// How the card is connected, e.g. "pci", "usb" or "bluetooth"
+    bus: Option<String>,
+    form_factor: Option<String>,
+    icon_name: Option<String>,
+    active_profile: Option<Profile>,
+    profiles: Vec<Profile>,
+    ports: Vec<Port>,
+}
+
+impl From<&CardInfo<'_>> for Card {
+    fn from(value: &CardInfo) -> Self {
+        Card {
+            index: value.index,
+            name: value.name.as_ref().map(|name| name.to_string()),
+            description: value.proplist.get_str(properties::DEVICE_DESCRIPTION),
+            bus: value.proplist.get_str(properties::DEVICE_BUS),
+            form_factor: value.proplist.get_str(properties::DEVICE_FORM_FACTOR),
+            icon_name: value.proplist.get_str(properties::DEVICE_ICON_NAME),
+            active_profile: value
+                .active_profile
+                .as_ref()
+                .map(|profile| Profile::from(profile.as_ref())),
+            profiles: value.profiles.iter().map(Profile::from).collect(),
+            ports: value
+                .ports
+                .iter()
+                .map(|port| Port::new(&port.name, &port.description, port.available))
+                .collect(),
+        }
+    }
+}
+
+/// Every card the server knows about, kept up to date from subscription events
+#[derive(Default)]
+struct Cards {
+    cards: BTreeMap<u32, Card>,
+}
+
+impl Listing for Cards {
+    fn print(&mut self) {
+        crate::print(&Some(self.cards.values().collect::<Vec<_>>()));
+    }
+}
+
+impl Cards {
+    fn insert(&mut self, card: &CardInfo) {
+        self.cards.insert(card.index, Card::from(card));
+    }
+}
+
+/// Queries all cards once and returns what's needed to keep them up to date
+pub fn listen(introspector: Rc<Mutex<Introspector>>) -> (InterestMaskSet, SubscribeCallback) {
+    listen_listing(
+        introspector,
+        InterestMaskSet::CARD,
+        |d, cards: &Shared<Cards>| {
+            let cards = Rc::clone(cards);
+            d.get_card_info_list(move |list| cards.borrow_mut().list(list, Cards::insert));
+            1
+        },
+        |d, cards, facility, operation, index| match (facility, operation) {
+            (Some(Facility::Card), Some(Operation::Removed)) => {
+                cards.borrow_mut().update(|cards| {
+                    cards.cards.remove(&index);
+                });
+            }
+            (Some(Facility::Card), _) => {
+                let cards = Rc::clone(cards);
+                d.get_card_info_by_index(index, move |list| {
+                    cards.borrow_mut().changed(list, Cards::insert)
+                });
+            }
+            _ => {}
+        },
+    )
+}
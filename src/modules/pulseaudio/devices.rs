@@ -0,0 +1,177 @@
+use std::{collections::BTreeMap, rc::Rc, sync::Mutex};
+
+use pulse::{
+    context::{
+        introspect::{Introspector, SinkInfo, SourceInfo},
+        subscribe::{Facility, InterestMaskSet, Operation},
+    },
+    proplist::{properties, Proplist},
+};
+use serde::Serialize;
+
+use super::{listen_listing, percent, Listing, Port, Shared, SubscribeCallback, VolumeDetails};
+
+#[derive(Debug, Serialize)]
+pub(super) struct Device {
+    pub(super) index: u32,
+    pub(super) name: Option<String>,
+    pub(super) description: Option<String>,
+    pub(super) volume: Option<u32>,
+    pub(super) muted: bool,
+    #[serde(flatten)]
+    pub(super) details: VolumeDetails,
+    pub(super) active_port: Option<Port>,
+    pub(super) ports: Vec<Port>,
+    pub(super) form_factor: Option<String>,
+    pub(super) icon_name: Option<String>,
+    /// Only set for sources, monitors just capture what's played on a sink
+    pub(super) monitor: bool,
+    pub(super) is_default: bool,
+}
+
+impl Device {
+    fn set_properties(&mut self, proplist: &Proplist) {
+        self.form_factor = proplist.get_str(properties::DEVICE_FORM_FACTOR);
+        self.icon_name = proplist.get_str(properties::DEVICE_ICON_NAME);
+    }
+}
+
+/// Sinks and sources have the same fields without sharing a type, so the conversion is written
+/// once for both
+macro_rules! device_from {
+    ($info:ident, monitor: |$value:ident| $monitor:expr) => {
+        impl From<&$info<'_>> for Device {
+            fn from($value: &$info) -> Self {
+                let mut device = Device {
+                    index: $value.index,
+                    name: $value.name.as_ref().map(|name| name.to_string()),
+                    description: $value.description.as_ref().map(|d| d.to_string()),
+                    volume: percent($value.volume.avg()),
+                    muted: $value.mute,
+                    details: VolumeDetails::new(
+                        &$value.volume,
+                        &$value.channel_map,
+                        $value.base_volume,
+                    ),
+                    active_port: $value
+                        .active_port
+                        .as_ref()
+                        .map(|port| Port::new(&port.name, &port.description, port.available)),
+                    ports: $value
+                        .ports
+                        .iter()
+                        .map(|port| Port::new(&port.name, &port.description, port.available))
+                        .collect(),
+                    form_factor: None,
+                    icon_name: None,
+                    monitor: $monitor,
+                    is_default: false,
+                };
+                device.set_properties(&$value.proplist);
+                device
+            }
+        }
+    };
+}
+
+device_from!(SinkInfo, monitor: |sink| false);
+device_from!(SourceInfo, monitor: |source| source.monitor_of_sink.is_some());
+
+#[derive(Debug, Serialize)]
+pub(super) struct Data<'a> {
+    pub(super) sinks: Vec<&'a Device>,
+    pub(super) sources: Vec<&'a Device>,
+}
+
+/// Every sink and source the server knows about, kept up to date from subscription events
+#[derive(Default)]
+struct Devices {
+    default_sink: Option<String>,
+    default_source: Option<String>,
+    sinks: BTreeMap<u32, Device>,
+    sources: BTreeMap<u32, Device>,
+}
+
+impl Listing for Devices {
+    fn print(&mut self) {
+        for sink in self.sinks.values_mut() {
+            sink.is_default = sink.name.is_some() && sink.name == self.default_sink;
+        }
+        for source in self.sources.values_mut() {
+            source.is_default = source.name.is_some() && source.name == self.default_source;
+        }
+        crate::print(&Some(Data {
+            sinks: self.sinks.values().collect(),
+            sources: self.sources.values().collect(),
+        }));
+    }
+}
+
+impl Devices {
+    fn insert_sink(&mut self, sink: &SinkInfo) {
+        self.sinks.insert(sink.index, Device::from(sink));
+    }
+
+    fn insert_source(&mut self, source: &SourceInfo) {
+        self.sources.insert(source.index, Device::from(source));
+    }
+}
+
+fn update_server(introspector: &Introspector, devices: &Shared<Devices>, initial: bool) {
+    let devices = Rc::clone(devices);
+    introspector.get_server_info(move |info| {
+        let mut devices = devices.borrow_mut();
+        if initial {
+            devices.pending -= 1;
+        }
+        devices.update(|devices| {
+            devices.default_sink = info.default_sink_name.as_ref().map(|n| n.to_string());
+            devices.default_source = info.default_source_name.as_ref().map(|n| n.to_string());
+        });
+    });
+}
+
+/// Queries all devices once and returns what's needed to keep them up to date
+pub fn listen(introspector: Rc<Mutex<Introspector>>) -> (InterestMaskSet, SubscribeCallback) {
+    let interest = InterestMaskSet::SINK | InterestMaskSet::SOURCE | InterestMaskSet::SERVER;
+    listen_listing(
+        introspector,
+        interest,
+        |d, devices: &Shared<Devices>| {
+            let sinks = Rc::clone(devices);
+            d.get_sink_info_list(move |list| sinks.borrow_mut().list(list, Devices::insert_sink));
+            let sources = Rc::clone(devices);
+            d.get_source_info_list(move |list| {
+                sources.borrow_mut().list(list, Devices::insert_source)
+            });
+            update_server(d, devices, true);
+            3
+        },
+        |d, devices, facility, operation, index| match (facility, operation) {
+            (Some(Facility::Server), _) => update_server(d, devices, false),
+            (Some(Facility::Sink), Some(Operation::Removed)) => {
+                devices.borrow_mut().update(|devices| {
+                    devices.sinks.remove(&index);
+                });
+            }
+            (Some(Facility::Sink), _) => {
+                let devices = Rc::clone(devices);
+                d.get_sink_info_by_index(index, move |list| {
+                    devices.borrow_mut().changed(list, Devices::insert_sink)
+                });
+            }
+            (Some(Facility::Source), Some(Operation::Removed)) => {
+                devices.borrow_mut().update(|devices| {
+                    devices.sources.remove(&index);
+                });
+            }
+            (Some(Facility::Source), _) => {
+                let devices = Rc::clone(devices);
+                d.get_source_info_by_index(index, move |list| {
+                    devices.borrow_mut().changed(list, Devices::insert_source)
+                });
+            }
+            _ => {}
+        },
+    )
+}
@@ -0,0 +1,561 @@
+use anyhow::{anyhow, Result};
+use std::{
+    borrow::Cow,
+    cell::RefCell,
+    rc::Rc,
+    sync::{Arc, Mutex},
+    thread::sleep,
+    time::Duration,
+};
+
+use clap::{Args, Subcommand, ValueEnum};
+use pulse::{
+    callbacks::ListResult,
+    channelmap::{Map, Position},
+    context::{
+        introspect::{Introspector, SinkInfo, SourceInfo},
+        subscribe::{Facility, InterestMaskSet, Operation},
+        Context,
+    },
+    def::PortAvailable,
+    mainloop::standard::{IterateResult, Mainloop},
+    volume::{ChannelVolumes, Volume, VolumeDB},
+};
+use serde::Serialize;
+
+use crate::Module;
+
+mod cards;
+mod devices;
+#[cfg(feature = "pipewire")]
+mod pipewire;
+mod recording;
+mod streams;
+
+/// What gets called for every event the context is subscribed to
+type SubscribeCallback = Box<dyn FnMut(Option<Facility>, Option<Operation>, u32) + 'static>;
+
+#[derive(Args)]
+pub struct PulseAudioOpts {
+    #[command(subcommand)]
+    mode: Option<PulseAudioMode>,
+    /// Talk to pipewire directly instead of through pipewire-pulse, needs the pipewire feature
+    #[arg(long, value_enum, default_value_t = Backend::Pulse)]
+    backend: Backend,
+}
+
+impl PulseAudioOpts {
+    /// Why these options can't be followed, if they can't, retrying wouldn't help in that case
+    pub fn unsupported(&self) -> Option<&'static str> {
+        match (self.backend, self.mode.unwrap_or_default()) {
+            (Backend::Pulse, _) if !cfg!(feature = "pulseaudio") => Some("Feature not enabled"),
+            (Backend::Pipewire, _) if !cfg!(feature = "pipewire") => Some("Feature not enabled"),
+            (Backend::Pipewire, PulseAudioMode::Recording | PulseAudioMode::Cards) => {
+                Some("This mode isn't supported by the pipewire backend")
+            }
+            _ => None,
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, PartialEq)]
+pub enum Backend {
+    Pulse,
+    Pipewire,
+}
+
+#[derive(Subcommand, Clone, Copy, Default)]
+pub enum PulseAudioMode {
+    /// The default output device, e.g. speakers or headphones (default)
+    #[default]
+    Sink,
+    /// The default input device, e.g. a microphone
+    Source,
+    /// Every sink and source, e.g. for a device picker
+    Devices,
+    /// Every application playing audio, e.g. for a mixer
+    Streams,
+    /// Every application recording from a microphone, e.g. for a privacy indicator
+    Recording,
+    /// Every sound card with its profiles and ports, e.g. to see the bluetooth headset mode
+    Cards,
+}
+
+/// Whether a device plays or records audio
+#[derive(Clone, Copy)]
+enum Kind {
+    Sink,
+    Source,
+}
+
+/// Why the state was printed, e.g. so an OSD only pops up when the user changes the volume
+#[derive(Debug, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum Event {
+    /// The first print after starting
+    Initial,
+    VolumeChanged,
+    MuteChanged,
+    /// Another device became the default, e.g. when headphones are plugged in
+    DefaultChanged,
+    /// A device was added, it doesn't have to be the default one
+    DeviceAdded,
+    /// Anything else about the device changed, e.g. the active port
+    Changed,
+}
+
+/// The default device being followed and what was last printed for it
+#[derive(Default)]
+struct Followed {
+    index: Option<u32>,
+    volume: Option<u32>,
+    muted: bool,
+}
+
+impl Followed {
+    /// Remembers the state of the default device and returns how it changed since the last print
+    fn update(&mut self, index: u32, volume: Option<u32>, muted: bool, added: bool) -> Event {
+        let event = match self.index {
+            None => Event::Initial,
+            Some(followed) if followed != index => Event::DefaultChanged,
+            _ if added => Event::DeviceAdded,
+            _ if self.volume != volume => Event::VolumeChanged,
+            _ if self.muted != muted => Event::MuteChanged,
+            _ => Event::Changed,
+        };
+        *self = Followed {
+            index: Some(index),
+            volume,
+            muted,
+        };
+        event
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct Data {
+    event: Event,
+    volume: u32,
+    muted: bool,
+    #[serde(flatten)]
+    details: VolumeDetails,
+    active_port: Option<Port>,
+}
+
+/// Where sound comes out of or goes into a device, e.g. the headphone jack
+#[derive(Debug, Serialize)]
+struct Port {
+    name: Option<String>,
+    description: Option<String>,
+    /// "yes", "no" or "unknown", e.g. whether headphones are plugged in
+    available: &'static str,
+}
+
+impl Port {
+    fn new(
+        name: &Option<Cow<str>>,
+        description: &Option<Cow<str>>,
+        available: PortAvailable,
+    ) -> Self {
+        Port {
+            name: name.as_ref().map(|name| name.to_string()),
+            description: description.as_ref().map(|d| d.to_string()),
+            available: match available {
+                PortAvailable::Yes => "yes",
+                PortAvailable::No => "no",
+                PortAvailable::Unknown => "unknown",
+            },
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct Channel {
+    position: Option<String>,
+    volume: Option<u32>,
+    volume_db: Option<f64>,
+}
+
+/// Everything about a device's volume beyond the average in percent
+#[derive(Debug, Serialize)]
+struct VolumeDetails {
+    volume_db: Option<f64>,
+    channels: Vec<Channel>,
+    balance: f32,
+    /// The volume at which the hardware isn't amplifying or attenuating, if it's known
+    base_volume: Option<u32>,
+}
+
+impl VolumeDetails {
+    fn new(volume: &ChannelVolumes, map: &Map, base_volume: Volume) -> Self {
+        VolumeDetails {
+            volume_db: decibels(volume.avg()),
+            channels: volume
+                .get()
+                .iter()
+                .zip(map.get())
+                .map(|(volume, position)| Channel {
+                    position: Position::to_string(*position).map(|name| name.to_string()),
+                    volume: percent(*volume),
+                    volume_db: decibels(*volume),
+                })
+                .collect(),
+            balance: volume.get_balance(map),
+            base_volume: percent(base_volume),
+        }
+    }
+}
+
+pub struct Connection {
+    cnxt: Context,
+    mnlp: Mainloop,
+}
+
+impl Connection {
+    fn new(timeout: u64) -> Result<Self> {
+        let mnlp = Mainloop::new().unwrap();
+        for _ in 0..10 {
+            let mut cnxt = Context::new(&mnlp, "pfui_listener").unwrap();
+            if cnxt
+                .connect(None, pulse::context::FlagSet::NOAUTOSPAWN, None)
+                .is_ok()
+            {
+                return Ok(Self { cnxt, mnlp });
+            }
+            sleep(Duration::from_secs(timeout));
+        }
+        Err(anyhow!("Timed out creating connection"))
+    }
+    fn connect(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        loop {
+            match self.mnlp.iterate(false) {
+                IterateResult::Err(e) => {
+                    return Err(Box::new(e));
+                }
+                IterateResult::Quit(_) => {
+                    return Err(Box::new(pulse::error::Code::BadState));
+                }
+                IterateResult::Success(_) => {}
+            }
+            match self.cnxt.get_state() {
+                pulse::context::State::Ready => {
+                    return Ok(());
+                }
+                pulse::context::State::Failed | pulse::context::State::Terminated => {
+                    return Err(Box::new(pulse::error::Code::BadState));
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Converts a volume to percent of the normal volume the same way pulseaudio prints it, this can
+/// be over 100 if it's amplified and is null for invalid volumes
+fn percent(volume: Volume) -> Option<u32> {
+    volume.print().trim().strip_suffix('%')?.parse().ok()
+}
+
+/// Converts a volume to decibels, this is null when the volume is 0 since that's negative
+/// infinity, muting doesn't change the volume
+fn decibels(volume: Volume) -> Option<f64> {
+    let decibels = VolumeDB::from(volume).0;
+    decibels.is_finite().then_some(decibels)
+}
+
+fn print_state(
+    volume: &ChannelVolumes,
+    map: &Map,
+    base_volume: Volume,
+    state: bool,
+    active_port: Option<Port>,
+    event: Event,
+) {
+    match percent(volume.avg()) {
+        Some(percent) => crate::print(&Some(Data {
+            event,
+            volume: percent,
+            muted: state,
+            details: VolumeDetails::new(volume, map, base_volume),
+            active_port,
+        })),
+        None => crate::print::<Data>(&None),
+    }
+}
+
+fn print_sink(sink: &SinkInfo, event: Event) {
+    let active_port = sink
+        .active_port
+        .as_ref()
+        .map(|port| Port::new(&port.name, &port.description, port.available));
+    print_state(
+        &sink.volume,
+        &sink.channel_map,
+        sink.base_volume,
+        sink.mute,
+        active_port,
+        event,
+    );
+}
+
+fn print_source(source: &SourceInfo, event: Event) {
+    let active_port = source
+        .active_port
+        .as_ref()
+        .map(|port| Port::new(&port.name, &port.description, port.available));
+    print_state(
+        &source.volume,
+        &source.channel_map,
+        source.base_volume,
+        source.mute,
+        active_port,
+        event,
+    );
+}
+
+/// Looks up the current default device and prints its state if it's not the one being followed
+fn update_default(
+    introspector: &Introspector,
+    kind: Kind,
+    followed: &Arc<std::sync::RwLock<Followed>>,
+) {
+    let followed_c = followed.clone();
+    // Returns the event to print if the default device has changed
+    let update = move |index: u32, volume: &ChannelVolumes, muted: bool| {
+        if let Ok(mut followed) = followed_c.write() {
+            if followed.index != Some(index) {
+                return Some(followed.update(index, percent(volume.avg()), muted, false));
+            }
+        }
+        None
+    };
+    match kind {
+        Kind::Sink => {
+            introspector.get_sink_info_by_name("@DEFAULT_SINK@", move |list| {
+                if let ListResult::Item(sink) = list {
+                    if let Some(event) = update(sink.index, &sink.volume, sink.mute) {
+                        print_sink(sink, event);
+                    }
+                }
+            });
+        }
+        Kind::Source => {
+            introspector.get_source_info_by_name("@DEFAULT_SOURCE@", move |list| {
+                if let ListResult::Item(source) = list {
+                    if let Some(event) = update(source.index, &source.volume, source.mute) {
+                        print_source(source, event);
+                    }
+                }
+            });
+        }
+    }
+}
+
+/// Prints the state of the device with the given index, `added` is set when the print is caused
+/// by another device showing up
+fn update_device(
+    introspector: &Introspector,
+    kind: Kind,
+    index: u32,
+    followed: &Arc<std::sync::RwLock<Followed>>,
+    added: bool,
+) {
+    let followed_c = followed.clone();
+    let update = move |index: u32, volume: &ChannelVolumes, muted: bool| {
+        followed_c
+            .write()
+            .map(|mut followed| followed.update(index, percent(volume.avg()), muted, added))
+            .unwrap_or(Event::Changed)
+    };
+    match kind {
+        Kind::Sink => {
+            introspector.get_sink_info_by_index(index, move |s| {
+                if let ListResult::Item(item) = s {
+                    print_sink(item, update(item.index, &item.volume, item.mute));
+                }
+            });
+        }
+        Kind::Source => {
+            introspector.get_source_info_by_index(index, move |s| {
+                if let ListResult::Item(item) = s {
+                    print_source(item, update(item.index, &item.volume, item.mute));
+                }
+            });
+        }
+    }
+}
+
+/// Prints the state of the default device and returns what's needed to follow it
+fn listen_default(
+    introspector: Rc<Mutex<Introspector>>,
+    kind: Kind,
+) -> (InterestMaskSet, SubscribeCallback) {
+    let followed = Arc::new(std::sync::RwLock::new(Followed::default()));
+    // print the data for initialization
+    update_default(&introspector.lock().unwrap(), kind, &followed);
+    // Server events are sent when the default device changes, e.g. when headphones are
+    // plugged in
+    let interest = match kind {
+        Kind::Sink => InterestMaskSet::SINK | InterestMaskSet::SERVER,
+        Kind::Source => InterestMaskSet::SOURCE | InterestMaskSet::SERVER,
+    };
+    let callback: SubscribeCallback =
+        Box::new(
+            move |facility, operation, index| match (facility, operation) {
+                (Some(Facility::Server), _) => {
+                    update_default(&introspector.lock().unwrap(), kind, &followed);
+                }
+                // The default device is printed again so an OSD can show that something was added,
+                // if the new device becomes the default a server event follows
+                (Some(Facility::Sink | Facility::Source), Some(Operation::New)) => {
+                    let default_index = followed.read().ok().and_then(|followed| followed.index);
+                    if let Some(default_index) = default_index {
+                        update_device(
+                            &introspector.lock().unwrap(),
+                            kind,
+                            default_index,
+                            &followed,
+                            true,
+                        );
+                    }
+                }
+                (Some(Facility::Sink | Facility::Source), _) => {
+                    let is_default = matches!(
+                        followed.read().as_deref(),
+                        Ok(Followed { index: Some(default_index), .. }) if *default_index == index
+                    );
+                    if is_default {
+                        update_device(&introspector.lock().unwrap(), kind, index, &followed, false);
+                    }
+                }
+                _ => {}
+            },
+        );
+    (interest, callback)
+}
+
+/// The state of a mode that lists everything of some kind, e.g. every stream
+trait Listing: Default {
+    fn print(&mut self);
+}
+
+/// A listing shared between the introspection callbacks, nothing is printed before the initial
+/// queries have finished
+struct Listed<T> {
+    state: T,
+    /// How many of the initial queries haven't finished yet
+    pending: u8,
+}
+
+type Shared<T> = Rc<RefCell<Listed<T>>>;
+
+impl<T: Listing> Listed<T> {
+    fn print(&mut self) {
+        if self.pending == 0 {
+            self.state.print();
+        }
+    }
+
+    /// Changes the state and prints it
+    fn update(&mut self, change: impl FnOnce(&mut T)) {
+        change(&mut self.state);
+        self.print();
+    }
+
+    /// Handles a result of one of the initial queries, the state is printed once all have ended
+    fn list<I>(&mut self, result: ListResult<I>, insert: impl FnOnce(&mut T, I)) {
+        match result {
+            ListResult::Item(item) => insert(&mut self.state, item),
+            ListResult::End | ListResult::Error => {
+                self.pending -= 1;
+                self.print();
+            }
+        }
+    }
+
+    /// Handles the result of querying something that changed
+    fn changed<I>(&mut self, result: ListResult<I>, insert: impl FnOnce(&mut T, I)) {
+        if let ListResult::Item(item) = result {
+            self.update(|state| insert(state, item));
+        }
+    }
+}
+
+/// Starts the initial queries of a listing mode and returns what's needed to keep it up to date,
+/// `query` returns how many queries it started and `on_event` handles subscription events
+fn listen_listing<T: Listing + 'static>(
+    introspector: Rc<Mutex<Introspector>>,
+    interest: InterestMaskSet,
+    query: impl FnOnce(&Introspector, &Shared<T>) -> u8,
+    on_event: impl Fn(&Introspector, &Shared<T>, Option<Facility>, Option<Operation>, u32) + 'static,
+) -> (InterestMaskSet, SubscribeCallback) {
+    let listing = Rc::new(RefCell::new(Listed {
+        state: T::default(),
+        pending: 0,
+    }));
+    // The callbacks only run once the mainloop does, so they can't see the count before it's set
+    let pending = query(&introspector.lock().unwrap(), &listing);
+    listing.borrow_mut().pending = pending;
+    let callback: SubscribeCallback = Box::new(move |facility, operation, index| {
+        on_event(
+            &introspector.lock().unwrap(),
+            &listing,
+            facility,
+            operation,
+            index,
+        );
+    });
+    (interest, callback)
+}
+
+pub struct PulseAudio {
+    mode: PulseAudioMode,
+    backend: Backend,
+}
+
+impl PulseAudio {
+    pub fn new(opts: &PulseAudioOpts) -> Self {
+        Self {
+            mode: opts.mode.unwrap_or_default(),
+            backend: opts.backend,
+        }
+    }
+}
+
+impl Module for PulseAudio {
+    type Connection = Connection;
+    fn connect(&mut self, timeout: u64) -> Result<Self::Connection> {
+        Connection::new(timeout)
+    }
+
+    fn start(&mut self, timeout: u64) -> Result<()> {
+        if self.backend == Backend::Pipewire {
+            #[cfg(feature = "pipewire")]
+            return pipewire::start(self.mode, timeout);
+            #[cfg(not(feature = "pipewire"))]
+            unreachable!("rejected by PulseAudioOpts::unsupported");
+        }
+        let mut conn = self.connect(timeout)?;
+        if conn.connect().is_err() {
+            return Err(anyhow!("Error establishing connection"));
+        }
+        let introspector = Rc::new(Mutex::new(conn.cnxt.introspect()));
+        let (interest, callback) = match self.mode {
+            PulseAudioMode::Sink => listen_default(introspector, Kind::Sink),
+            PulseAudioMode::Source => listen_default(introspector, Kind::Source),
+            PulseAudioMode::Devices => devices::listen(introspector),
+            PulseAudioMode::Streams => streams::listen(introspector),
+            PulseAudioMode::Recording => recording::listen(introspector),
+            PulseAudioMode::Cards => cards::listen(introspector),
+        };
+        conn.cnxt.subscribe(interest, |_| {});
+        conn.cnxt.set_subscribe_callback(Some(callback));
+        match conn.mnlp.run() {
+            Ok(_retval) => Ok(()),
+            Err((e, _retval)) => Err(anyhow::Error::new(e)),
+        }
+    }
+
+    #[allow(unused)]
+    fn output(&self, conn: &mut Self::Connection) {}
+}
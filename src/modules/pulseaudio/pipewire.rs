@@ -0,0 +1,523 @@
+use std::{cell::RefCell, collections::BTreeMap, rc::Rc, thread::sleep, time::Duration};
+
+use anyhow::{anyhow, Result};
+use pipewire::{
+    context::Context,
+    core::PW_ID_CORE,
+    main_loop::MainLoop,
+    metadata::{Metadata, MetadataListener},
+    node::{Node as NodeProxy, NodeListener, NodeState},
+    registry::{GlobalObject, Registry},
+    spa::{
+        param::ParamType,
+        pod::{deserialize::PodDeserializer, Pod, Value, ValueArray},
+        utils::{dict::DictRef, Id},
+    },
+    types::ObjectType,
+};
+
+use super::{devices, streams, Channel, Data, Event, Followed, PulseAudioMode, VolumeDetails};
+
+/// `SPA_PROP_mute` from spa/param/props.h
+const SPA_PROP_MUTE: u32 = 0x10004;
+/// `SPA_PROP_channelVolumes` from spa/param/props.h
+const SPA_PROP_CHANNEL_VOLUMES: u32 = 0x10008;
+/// `SPA_PROP_channelMap` from spa/param/props.h
+const SPA_PROP_CHANNEL_MAP: u32 = 0x1000b;
+
+/// The `spa_audio_channel` positions from spa/param/audio/raw.h that pulseaudio has a name for,
+/// named the same way pulseaudio does so the output is the same for both backends
+const POSITIONS: [(u32, &str); 19] = [
+    (2, "mono"),
+    (3, "front-left"),
+    (4, "front-right"),
+    (5, "front-center"),
+    (6, "lfe"),
+    (7, "side-left"),
+    (8, "side-right"),
+    (9, "front-left-of-center"),
+    (10, "front-right-of-center"),
+    (11, "rear-center"),
+    (12, "rear-left"),
+    (13, "rear-right"),
+    (14, "top-center"),
+    (15, "top-front-left"),
+    (16, "top-front-center"),
+    (17, "top-front-right"),
+    (18, "top-rear-left"),
+    (19, "top-rear-center"),
+    (20, "top-rear-right"),
+];
+
+/// The positions pulseaudio counts as left and right when calculating the balance
+const LEFT: [u32; 6] = [3, 7, 9, 12, 15, 18];
+const RIGHT: [u32; 6] = [4, 8, 10, 13, 17, 20];
+
+/// Pipewire stores linear volumes, while pulseaudio and volume sliders use a cubic scale
+fn cubic(linear: f32) -> f64 {
+    f64::from(linear).max(0.0).cbrt()
+}
+
+/// The same as the pulseaudio backend's percent, but for a volume on the cubic scale
+fn percent(cubic: f64) -> Option<u32> {
+    let percent = (cubic * 100.0).round();
+    (percent.is_finite() && percent <= f64::from(u32::MAX)).then_some(percent as u32)
+}
+
+/// The same as the pulseaudio backend's decibels, but for a volume on the cubic scale
+fn decibels(cubic: f64) -> Option<f64> {
+    let decibels = 60.0 * cubic.log10();
+    decibels.is_finite().then_some(decibels)
+}
+
+/// Averages volumes on the cubic scale, the same way pulseaudio does
+fn average(volumes: impl Iterator<Item = f64>) -> Option<f64> {
+    let (sum, count) = volumes.fold((0.0, 0), |(sum, count), volume| (sum + volume, count + 1));
+    (count > 0).then(|| sum / f64::from(count))
+}
+
+/// Ranges from -1 if only the left channels are audible to 1 for the right ones, like
+/// `pa_cvolume_get_balance`
+fn balance(channels: &[(f64, u32)]) -> f32 {
+    let side = |positions: &[u32]| {
+        average(
+            channels
+                .iter()
+                .filter(|(_, position)| positions.contains(position))
+                .map(|(volume, _)| *volume),
+        )
+    };
+    let (Some(left), Some(right)) = (side(&LEFT), side(&RIGHT)) else {
+        return 0.0;
+    };
+    if left == right {
+        0.0
+    } else if left > right {
+        (right / left - 1.0) as f32
+    } else {
+        (1.0 - left / right) as f32
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum NodeKind {
+    Sink,
+    Source,
+    Stream,
+}
+
+struct Node {
+    kind: NodeKind,
+    name: Option<String>,
+    description: Option<String>,
+    form_factor: Option<String>,
+    icon_name: Option<String>,
+    application_name: Option<String>,
+    binary: Option<String>,
+    media_name: Option<String>,
+    /// The linear volume of each channel, the same way pipewire stores it
+    volumes: Vec<f32>,
+    /// The `spa_audio_channel` position of each channel
+    positions: Vec<u32>,
+    muted: bool,
+    running: bool,
+}
+
+impl Node {
+    fn new(kind: NodeKind, props: &DictRef) -> Self {
+        let get = |key| props.get(key).map(str::to_owned);
+        Node {
+            kind,
+            name: get("node.name"),
+            description: get("node.description"),
+            form_factor: get("device.form-factor"),
+            icon_name: get("device.icon-name").or_else(|| get("application.icon-name")),
+            application_name: get("application.name"),
+            binary: get("application.process.binary"),
+            media_name: get("media.name"),
+            volumes: Vec::new(),
+            positions: Vec::new(),
+            muted: false,
+            running: false,
+        }
+    }
+
+    /// The positions of the channels, nodes without a channel map get the same default one
+    /// pulseaudio uses for mono and stereo
+    fn positions(&self) -> Vec<u32> {
+        match (self.positions.len(), self.volumes.len()) {
+            (n, m) if n == m => self.positions.clone(),
+            (_, 1) => vec![2],
+            (_, 2) => vec![3, 4],
+            _ => Vec::new(),
+        }
+    }
+
+    /// The average volume in percent along with the details, calculated the same way as with
+    /// the pulseaudio backend
+    fn volume(&self) -> (Option<u32>, VolumeDetails) {
+        let volumes: Vec<f64> = self.volumes.iter().map(|linear| cubic(*linear)).collect();
+        let positions = self.positions();
+        let average = average(volumes.iter().copied());
+        let details = VolumeDetails {
+            volume_db: average.and_then(decibels),
+            channels: volumes
+                .iter()
+                .enumerate()
+                .map(|(channel, volume)| Channel {
+                    position: positions.get(channel).and_then(|position| {
+                        POSITIONS
+                            .iter()
+                            .find(|(id, _)| id == position)
+                            .map(|(_, name)| (*name).to_owned())
+                    }),
+                    volume: percent(*volume),
+                    volume_db: decibels(*volume),
+                })
+                .collect(),
+            balance: balance(&volumes.iter().copied().zip(positions).collect::<Vec<_>>()),
+            base_volume: Some(100),
+        };
+        (average.and_then(percent), details)
+    }
+
+    fn device(&self, id: u32, is_default: bool) -> devices::Device {
+        let (volume, details) = self.volume();
+        devices::Device {
+            index: id,
+            name: self.name.clone(),
+            description: self.description.clone(),
+            volume,
+            muted: self.muted,
+            details,
+            active_port: None,
+            ports: Vec::new(),
+            form_factor: self.form_factor.clone(),
+            icon_name: self.icon_name.clone(),
+            monitor: false,
+            is_default,
+        }
+    }
+
+    fn stream(&self, id: u32, sink: Option<(u32, &Node)>) -> streams::Stream {
+        let (volume, _) = self.volume();
+        streams::Stream {
+            index: id,
+            application_name: self.application_name.clone(),
+            binary: self.binary.clone(),
+            icon_name: self.icon_name.clone(),
+            media_name: self.media_name.clone(),
+            volume,
+            muted: self.muted,
+            corked: !self.running,
+            sink: sink.map(|(id, _)| id),
+            sink_name: sink.and_then(|(_, sink)| sink.name.clone()),
+        }
+    }
+
+    /// Reads the volume, channel map and mute state from a `Props` param
+    fn update_props(&mut self, param: &Pod) {
+        let Ok((_, Value::Object(object))) =
+            PodDeserializer::deserialize_any_from(param.as_bytes())
+        else {
+            return;
+        };
+        for property in object.properties {
+            match (property.key, property.value) {
+                (SPA_PROP_MUTE, Value::Bool(muted)) => self.muted = muted,
+                (SPA_PROP_CHANNEL_VOLUMES, Value::ValueArray(ValueArray::Float(volumes))) => {
+                    self.volumes = volumes;
+                }
+                (SPA_PROP_CHANNEL_MAP, Value::ValueArray(ValueArray::Id(positions))) => {
+                    self.positions = positions.into_iter().map(|Id(position)| position).collect();
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Everything pipewire has told us about so far, kept up to date from registry events
+struct State {
+    mode: PulseAudioMode,
+    nodes: BTreeMap<u32, Node>,
+    /// Which node each link goes from and to, used to find the sink a stream is playing on
+    links: BTreeMap<u32, (u32, u32)>,
+    default_sink: Option<String>,
+    default_source: Option<String>,
+    /// What was printed last, every node change ends up here so this avoids printing duplicates
+    last: Option<String>,
+    followed: Followed,
+}
+
+impl State {
+    fn emit<T: serde::Serialize>(&mut self, data: &Option<T>) {
+        let json = serde_json::to_string(data).ok();
+        if json != self.last {
+            crate::print(data);
+            self.last = json;
+        }
+    }
+
+    fn default_name(&self, kind: NodeKind) -> Option<&String> {
+        match kind {
+            NodeKind::Sink => self.default_sink.as_ref(),
+            NodeKind::Source => self.default_source.as_ref(),
+            NodeKind::Stream => None,
+        }
+    }
+
+    fn print_default(&mut self, kind: NodeKind) {
+        let default = self.nodes.iter().find(|(_, node)| {
+            node.kind == kind
+                && node.name.is_some()
+                && node.name.as_ref() == self.default_name(kind)
+        });
+        let Some((&id, node)) = default else {
+            return;
+        };
+        let (Some(percent), details) = node.volume() else {
+            return self.emit::<Data>(&None);
+        };
+        let mut data = Data {
+            event: Event::Changed,
+            volume: percent,
+            muted: node.muted,
+            details,
+            active_port: None,
+        };
+        // The event isn't part of the comparison, otherwise every print would be a new one
+        let json = serde_json::to_string(&Some(&data)).ok();
+        if json == self.last {
+            return;
+        }
+        // Nodes show up one by one while starting, so device_added isn't reported here
+        data.event = self.followed.update(id, Some(percent), data.muted, false);
+        crate::print(&Some(data));
+        self.last = json;
+    }
+
+    fn devices(&self, kind: NodeKind) -> Vec<devices::Device> {
+        self.nodes
+            .iter()
+            .filter(|(_, node)| node.kind == kind)
+            .map(|(id, node)| {
+                let is_default =
+                    node.name.is_some() && node.name.as_ref() == self.default_name(kind);
+                node.device(*id, is_default)
+            })
+            .collect()
+    }
+
+    fn print(&mut self) {
+        match self.mode {
+            PulseAudioMode::Sink => self.print_default(NodeKind::Sink),
+            PulseAudioMode::Source => self.print_default(NodeKind::Source),
+            PulseAudioMode::Devices => {
+                let (sinks, sources) =
+                    (self.devices(NodeKind::Sink), self.devices(NodeKind::Source));
+                self.emit(&Some(devices::Data {
+                    sinks: sinks.iter().collect(),
+                    sources: sources.iter().collect(),
+                }));
+            }
+            PulseAudioMode::Streams => {
+                let streams: Vec<streams::Stream> = self
+                    .nodes
+                    .iter()
+                    .filter(|(_, node)| node.kind == NodeKind::Stream)
+                    .map(|(id, node)| {
+                        let sink = self
+                            .links
+                            .values()
+                            .filter(|(output, _)| output == id)
+                            .find_map(|(_, input)| {
+                                let sink = self.nodes.get(input)?;
+                                (sink.kind == NodeKind::Sink).then_some((*input, sink))
+                            });
+                        node.stream(*id, sink)
+                    })
+                    .collect();
+                self.emit(&Some(streams));
+            }
+            PulseAudioMode::Recording | PulseAudioMode::Cards => {}
+        }
+    }
+
+    /// Reads a default node from the "default" metadata, the value looks like `{"name":"..."}`
+    fn update_default(&mut self, key: Option<&str>, value: Option<&str>) {
+        let name = value
+            .and_then(|value| serde_json::from_str::<serde_json::Value>(value).ok())
+            .and_then(|value| value.get("name")?.as_str().map(str::to_owned));
+        match key {
+            Some("default.audio.sink") => self.default_sink = name,
+            Some("default.audio.source") => self.default_source = name,
+            _ => return,
+        }
+        self.print();
+    }
+}
+
+/// Proxies have to be kept around, otherwise pipewire stops sending events for their objects
+#[derive(Default)]
+struct Proxies {
+    nodes: BTreeMap<u32, (NodeProxy, NodeListener)>,
+    metadata: Option<(Metadata, MetadataListener)>,
+}
+
+fn add_node(
+    registry: &Registry,
+    global: &GlobalObject<&DictRef>,
+    kind: NodeKind,
+    state: &Rc<RefCell<State>>,
+    proxies: &Rc<RefCell<Proxies>>,
+) {
+    let Some(props) = global.props else {
+        return;
+    };
+    let Ok(node) = registry.bind::<NodeProxy, _>(global) else {
+        return;
+    };
+    let id = global.id;
+    state.borrow_mut().nodes.insert(id, Node::new(kind, props));
+    let param_state = Rc::clone(state);
+    let info_state = Rc::clone(state);
+    let listener = node
+        .add_listener_local()
+        .info(move |info| {
+            let mut state = info_state.borrow_mut();
+            if let Some(node) = state.nodes.get_mut(&id) {
+                node.running = matches!(info.state(), NodeState::Running);
+            }
+            state.print();
+        })
+        .param(move |_seq, _id, _index, _next, param| {
+            let mut state = param_state.borrow_mut();
+            if let (Some(node), Some(param)) = (state.nodes.get_mut(&id), param) {
+                node.update_props(param);
+            }
+            state.print();
+        })
+        .register();
+    node.subscribe_params(&[ParamType::Props]);
+    proxies.borrow_mut().nodes.insert(id, (node, listener));
+}
+
+fn add_metadata(
+    registry: &Registry,
+    global: &GlobalObject<&DictRef>,
+    state: &Rc<RefCell<State>>,
+    proxies: &Rc<RefCell<Proxies>>,
+) {
+    let Ok(metadata) = registry.bind::<Metadata, _>(global) else {
+        return;
+    };
+    let state = Rc::clone(state);
+    let listener = metadata
+        .add_listener_local()
+        .property(move |_subject, key, _type, value| {
+            state.borrow_mut().update_default(key, value);
+            0
+        })
+        .register();
+    proxies.borrow_mut().metadata = Some((metadata, listener));
+}
+
+/// Listens to pipewire directly instead of going through pipewire-pulse, the output is the same.
+/// Recording and cards aren't supported, `PulseAudioOpts::unsupported` rejects them beforehand
+pub fn start(mode: PulseAudioMode, timeout: u64) -> Result<()> {
+    pipewire::init();
+    let mainloop = MainLoop::new(None)?;
+    let context = Context::new(&mainloop)?;
+    let core = match context.connect(None) {
+        Ok(core) => core,
+        Err(e) => {
+            // Waits before the next attempt, e.g. while pipewire is restarting
+            sleep(Duration::from_secs(timeout));
+            return Err(e.into());
+        }
+    };
+    // Errors about the core are fatal, e.g. when pipewire goes away, so the mainloop is stopped
+    // to connect again
+    let error = Rc::new(RefCell::new(None));
+    let core_error = Rc::clone(&error);
+    let mainloop_weak = mainloop.downgrade();
+    let _core_listener = core
+        .add_listener_local()
+        .error(move |id, _seq, res, message| {
+            if id != PW_ID_CORE {
+                return;
+            }
+            *core_error.borrow_mut() = Some(format!("{message} ({res})"));
+            if let Some(mainloop) = mainloop_weak.upgrade() {
+                mainloop.quit();
+            }
+        })
+        .register();
+    let registry = Rc::new(core.get_registry()?);
+    let state = Rc::new(RefCell::new(State {
+        mode,
+        nodes: BTreeMap::new(),
+        links: BTreeMap::new(),
+        default_sink: None,
+        default_source: None,
+        last: None,
+        followed: Followed::default(),
+    }));
+    let proxies = Rc::new(RefCell::new(Proxies::default()));
+
+    let registry_weak = Rc::downgrade(&registry);
+    let global_state = Rc::clone(&state);
+    let global_proxies = Rc::clone(&proxies);
+    let remove_state = Rc::clone(&state);
+    let remove_proxies = Rc::clone(&proxies);
+    let _listener = registry
+        .add_listener_local()
+        .global(move |global| {
+            let Some(registry) = registry_weak.upgrade() else {
+                return;
+            };
+            let props = global.props.as_ref();
+            match global.type_ {
+                ObjectType::Node => {
+                    let kind = match props.and_then(|props| props.get("media.class")) {
+                        Some("Audio/Sink") => NodeKind::Sink,
+                        Some("Audio/Source") => NodeKind::Source,
+                        Some("Stream/Output/Audio") => NodeKind::Stream,
+                        _ => return,
+                    };
+                    add_node(&registry, global, kind, &global_state, &global_proxies);
+                }
+                ObjectType::Metadata
+                    if props.and_then(|props| props.get("metadata.name")) == Some("default") =>
+                {
+                    add_metadata(&registry, global, &global_state, &global_proxies);
+                }
+                ObjectType::Link => {
+                    let node = |key| props?.get(key)?.parse::<u32>().ok();
+                    if let (Some(output), Some(input)) =
+                        (node("link.output.node"), node("link.input.node"))
+                    {
+                        let mut state = global_state.borrow_mut();
+                        state.links.insert(global.id, (output, input));
+                        state.print();
+                    }
+                }
+                _ => {}
+            }
+        })
+        .global_remove(move |id| {
+            remove_proxies.borrow_mut().nodes.remove(&id);
+            let mut state = remove_state.borrow_mut();
+            if state.nodes.remove(&id).is_some() || state.links.remove(&id).is_some() {
+                state.print();
+            }
+        })
+        .register();
+
+    mainloop.run();
+    match error.take() {
+        Some(error) => Err(anyhow!("pipewire error: {error}")),
+        None => Ok(()),
+    }
+}
@@ -0,0 +1,155 @@
+use std::{collections::BTreeMap, rc::Rc, sync::Mutex};
+
+use pulse::{
+    callbacks::ListResult,
+    context::{
+        introspect::{Introspector, SourceInfo, SourceOutputInfo},
+        subscribe::{Facility, InterestMaskSet, Operation},
+    },
+    proplist::properties,
+};
+use serde::Serialize;
+
+use super::{listen_listing, Listing, Shared, SubscribeCallback};
+
+#[derive(Debug, Serialize)]
+struct Recorder {
+    index: u32,
+    application_name: Option<String>,
+    binary: Option<String>,
+    icon_name: Option<String>,
+    /// Paused recordings are still listed, but aren't capturing anything
+    corked: bool,
+    source: u32,
+    source_name: Option<String>,
+    #[serde(skip)]
+    pid: Option<u32>,
+}
+
+impl From<&SourceOutputInfo<'_>> for Recorder {
+    fn from(value: &SourceOutputInfo) -> Self {
+        Recorder {
+            index: value.index,
+            application_name: value
+                .proplist
+                .get_str(properties::APPLICATION_NAME)
+                .or_else(|| value.name.as_ref().map(|name| name.to_string())),
+            binary: value
+                .proplist
+                .get_str(properties::APPLICATION_PROCESS_BINARY),
+            icon_name: value.proplist.get_str(properties::APPLICATION_ICON_NAME),
+            corked: value.corked,
+            source: value.source,
+            source_name: None,
+            pid: value
+                .proplist
+                .get_str(properties::APPLICATION_PROCESS_ID)
+                .and_then(|pid| pid.parse().ok()),
+        }
+    }
+}
+
+struct Source {
+    name: Option<String>,
+    monitor: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct Data<'a> {
+    recording: bool,
+    applications: Vec<&'a Recorder>,
+}
+
+/// Every application recording audio, along with the sources they could be recording from
+#[derive(Default)]
+struct Recorders {
+    sources: BTreeMap<u32, Source>,
+    recorders: BTreeMap<u32, Recorder>,
+}
+
+impl Listing for Recorders {
+    fn print(&mut self) {
+        for recorder in self.recorders.values_mut() {
+            recorder.source_name = self
+                .sources
+                .get(&recorder.source)
+                .and_then(|source| source.name.clone());
+        }
+        // Monitor sources only capture what's being played, e.g. for visualizers or peak meters
+        let applications: Vec<&Recorder> = self
+            .recorders
+            .values()
+            .filter(|recorder| {
+                !matches!(self.sources.get(&recorder.source), Some(source) if source.monitor)
+            })
+            .filter(|recorder| recorder.pid != Some(std::process::id()))
+            .collect();
+        crate::print(&Some(Data {
+            recording: applications.iter().any(|recorder| !recorder.corked),
+            applications,
+        }));
+    }
+}
+
+impl Recorders {
+    fn insert_source(&mut self, source: &SourceInfo) {
+        self.sources.insert(
+            source.index,
+            Source {
+                name: source.name.as_ref().map(|name| name.to_string()),
+                monitor: source.monitor_of_sink.is_some(),
+            },
+        );
+    }
+
+    fn insert_recorder(&mut self, recorder: &SourceOutputInfo) {
+        self.recorders
+            .insert(recorder.index, Recorder::from(recorder));
+    }
+}
+
+/// Queries all recording applications once and returns what's needed to keep them up to date
+pub fn listen(introspector: Rc<Mutex<Introspector>>) -> (InterestMaskSet, SubscribeCallback) {
+    listen_listing(
+        introspector,
+        InterestMaskSet::SOURCE_OUTPUT | InterestMaskSet::SOURCE,
+        |d, recorders: &Shared<Recorders>| {
+            let sources = Rc::clone(recorders);
+            d.get_source_info_list(move |list| {
+                sources.borrow_mut().list(list, Recorders::insert_source)
+            });
+            let outputs = Rc::clone(recorders);
+            d.get_source_output_info_list(move |list| {
+                outputs.borrow_mut().list(list, Recorders::insert_recorder)
+            });
+            2
+        },
+        |d, recorders, facility, operation, index| match (facility, operation) {
+            (Some(Facility::SourceOutput), Some(Operation::Removed)) => {
+                recorders.borrow_mut().update(|recorders| {
+                    recorders.recorders.remove(&index);
+                });
+            }
+            (Some(Facility::SourceOutput), _) => {
+                let recorders = Rc::clone(recorders);
+                d.get_source_output_info(index, move |list| {
+                    recorders
+                        .borrow_mut()
+                        .changed(list, Recorders::insert_recorder)
+                });
+            }
+            (Some(Facility::Source), Some(Operation::Removed)) => {
+                recorders.borrow_mut().state.sources.remove(&index);
+            }
+            (Some(Facility::Source), Some(Operation::New)) => {
+                let recorders = Rc::clone(recorders);
+                d.get_source_info_by_index(index, move |list| {
+                    if let ListResult::Item(source) = list {
+                        recorders.borrow_mut().state.insert_source(source);
+                    }
+                });
+            }
+            _ => {}
+        },
+    )
+}
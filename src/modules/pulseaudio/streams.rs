@@ -0,0 +1,122 @@
+use std::{collections::BTreeMap, rc::Rc, sync::Mutex};
+
+use pulse::{
+    callbacks::ListResult,
+    context::{
+        introspect::{Introspector, SinkInfo, SinkInputInfo},
+        subscribe::{Facility, InterestMaskSet, Operation},
+    },
+    def::INVALID_INDEX,
+    proplist::properties,
+};
+use serde::Serialize;
+
+use super::{listen_listing, percent, Listing, Shared, SubscribeCallback};
+
+#[derive(Debug, Serialize)]
+pub(super) struct Stream {
+    pub(super) index: u32,
+    pub(super) application_name: Option<String>,
+    pub(super) binary: Option<String>,
+    pub(super) icon_name: Option<String>,
+    pub(super) media_name: Option<String>,
+    pub(super) volume: Option<u32>,
+    pub(super) muted: bool,
+    /// Paused streams are still listed, but aren't playing anything
+    pub(super) corked: bool,
+    /// Missing while the stream isn't connected to a sink, e.g. while it's being moved
+    pub(super) sink: Option<u32>,
+    pub(super) sink_name: Option<String>,
+}
+
+impl From<&SinkInputInfo<'_>> for Stream {
+    fn from(value: &SinkInputInfo) -> Self {
+        Stream {
+            index: value.index,
+            application_name: value
+                .proplist
+                .get_str(properties::APPLICATION_NAME)
+                .or_else(|| value.name.as_ref().map(|name| name.to_string())),
+            binary: value
+                .proplist
+                .get_str(properties::APPLICATION_PROCESS_BINARY),
+            icon_name: value.proplist.get_str(properties::APPLICATION_ICON_NAME),
+            media_name: value.proplist.get_str(properties::MEDIA_NAME),
+            volume: percent(value.volume.avg()),
+            muted: value.mute,
+            corked: value.corked,
+            sink: (value.sink != INVALID_INDEX).then_some(value.sink),
+            sink_name: None,
+        }
+    }
+}
+
+/// Every playing stream, along with the names of the sinks they could be playing on
+#[derive(Default)]
+struct Streams {
+    sinks: BTreeMap<u32, String>,
+    streams: BTreeMap<u32, Stream>,
+}
+
+impl Listing for Streams {
+    fn print(&mut self) {
+        for stream in self.streams.values_mut() {
+            stream.sink_name = stream.sink.and_then(|sink| self.sinks.get(&sink).cloned());
+        }
+        crate::print(&Some(self.streams.values().collect::<Vec<_>>()));
+    }
+}
+
+impl Streams {
+    fn insert_sink(&mut self, sink: &SinkInfo) {
+        if let Some(name) = &sink.name {
+            self.sinks.insert(sink.index, name.to_string());
+        }
+    }
+
+    fn insert_stream(&mut self, stream: &SinkInputInfo) {
+        self.streams.insert(stream.index, Stream::from(stream));
+    }
+}
+
+/// Queries all streams once and returns what's needed to keep them up to date
+pub fn listen(introspector: Rc<Mutex<Introspector>>) -> (InterestMaskSet, SubscribeCallback) {
+    listen_listing(
+        introspector,
+        InterestMaskSet::SINK_INPUT | InterestMaskSet::SINK,
+        |d, streams: &Shared<Streams>| {
+            let sinks = Rc::clone(streams);
+            d.get_sink_info_list(move |list| sinks.borrow_mut().list(list, Streams::insert_sink));
+            let inputs = Rc::clone(streams);
+            d.get_sink_input_info_list(move |list| {
+                inputs.borrow_mut().list(list, Streams::insert_stream)
+            });
+            2
+        },
+        |d, streams, facility, operation, index| match (facility, operation) {
+            (Some(Facility::SinkInput), Some(Operation::Removed)) => {
+                streams.borrow_mut().update(|streams| {
+                    streams.streams.remove(&index);
+                });
+            }
+            (Some(Facility::SinkInput), _) => {
+                let streams = Rc::clone(streams);
+                d.get_sink_input_info(index, move |list| {
+                    streams.borrow_mut().changed(list, Streams::insert_stream)
+                });
+            }
+            (Some(Facility::Sink), Some(Operation::Removed)) => {
+                streams.borrow_mut().state.sinks.remove(&index);
+            }
+            (Some(Facility::Sink), Some(Operation::New)) => {
+                let streams = Rc::clone(streams);
+                d.get_sink_info_by_index(index, move |list| {
+                    if let ListResult::Item(sink) = list {
+                        streams.borrow_mut().state.insert_sink(sink);
+                    }
+                });
+            }
+            _ => {}
+        },
+    )
+}
@@ -1,20 +1,28 @@
-use std::{thread::sleep, time::Duration};
+use std::{cell::RefCell, collections::HashMap, env, fs, thread::sleep, time::Duration};
 
 use anyhow::Result;
+use clap::{Args, Subcommand};
 use serde::Serialize;
-use sway::{Connection, EventType, NodeType};
+use sway::{
+    Connection, Event, EventType, InputChange, InputEvent, NodeType, ShellType, WindowChange,
+    WindowEvent, WorkspaceChange, WorkspaceEvent,
+};
 
 use crate::Module;
 
 #[derive(Debug, Serialize)]
 struct Data {
     kbd_layout: Option<String>,
+    keyboards: Vec<Keyboard>,
     window_title: Option<String>,
+    window: Option<Window>,
     workspaces: Vec<Workspace>,
     binding_modes: Vec<BindingMode>,
+    outputs: Vec<Output>,
+    scratchpad: Scratchpad,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, Clone)]
 struct Workspace {
     id: i64,
     name: String,
@@ -24,85 +32,749 @@ struct Workspace {
     output: String,
 }
 
+#[derive(Debug, Serialize)]
+struct Keyboard {
+    identifier: String,
+    name: String,
+    layouts: Vec<Layout>,
+    active_index: Option<i32>,
+    active_layout: Option<String>,
+    active_code: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct Layout {
+    /// e.g. "English (US)"
+    name: String,
+    /// e.g. "us", also for variants of the layout
+    code: Option<String>,
+}
+
+impl Keyboard {
+    fn new(input: &sway::Input, codes: &HashMap<String, String>) -> Self {
+        let code = |name: &String| codes.get(name).cloned();
+        Keyboard {
+            identifier: input.identifier.clone(),
+            name: input.name.clone(),
+            layouts: input
+                .xkb_layout_names
+                .iter()
+                .map(|name| Layout {
+                    name: name.clone(),
+                    code: code(name),
+                })
+                .collect(),
+            active_index: input.xkb_active_layout_index,
+            active_layout: input.xkb_active_layout_name.clone(),
+            active_code: input.xkb_active_layout_name.as_ref().and_then(code),
+        }
+    }
+}
+
+/// Maps the names sway reports for layouts, e.g. "English (US)", to their codes, e.g. "us", using
+/// the list of layouts that comes with xkeyboard-config
+fn read_layout_codes() -> HashMap<String, String> {
+    let root = env::var("XKB_CONFIG_ROOT").unwrap_or_else(|_| "/usr/share/X11/xkb".to_string());
+    let Ok(list) = fs::read_to_string(format!("{root}/rules/evdev.lst")) else {
+        return HashMap::new();
+    };
+    let mut codes = HashMap::new();
+    let mut section = "";
+    for line in list.lines() {
+        if let Some(name) = line.strip_prefix("! ") {
+            section = name.trim();
+            continue;
+        }
+        let Some((key, description)) = line.trim().split_once(char::is_whitespace) else {
+            continue;
+        };
+        let description = description.trim();
+        match section {
+            "layout" => {
+                codes.insert(description.to_string(), key.to_string());
+            }
+            // Variants look like "intl  us: English (US, intl., with dead keys)"
+            "variant" => {
+                if let Some((layout, description)) = description.split_once(": ") {
+                    codes.insert(description.to_string(), layout.to_string());
+                }
+            }
+            _ => {}
+        }
+    }
+    codes
+}
+
 #[derive(Debug, Serialize)]
 struct BindingMode {
     name: String,
     active: bool,
+    /// Whether the name of the active mode should be parsed as pango markup, this is only known
+    /// once the mode has been changed
+    pango_markup: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct Window {
+    id: i64,
+    title: Option<String>,
+    /// Only set for native wayland windows
+    app_id: Option<String>,
+    /// Only set for Xwayland windows
+    class: Option<String>,
+    /// Only set for Xwayland windows
+    instance: Option<String>,
+    /// "xdg_shell" or "xwayland"
+    shell: Option<&'static str>,
+    pid: Option<i32>,
+    floating: bool,
+    fullscreen: bool,
+    sticky: bool,
+    marks: Vec<String>,
+    geometry: Geometry,
+    urgent: bool,
+}
+
+/// Where a window is, in the coordinates of the whole layout rather than of its output
+#[derive(Debug, Serialize)]
+struct Geometry {
+    x: i32,
+    y: i32,
+    width: i32,
+    height: i32,
 }
 
-fn flatten_nodes(node: &mut sway::Node) -> Vec<sway::Node> {
-    let mut result: Vec<sway::Node> = Vec::new();
-    for child in &mut node.nodes {
-        result.append(&mut flatten_nodes(child));
+impl From<&sway::Node> for Window {
+    fn from(node: &sway::Node) -> Self {
+        let properties = node.window_properties.as_ref();
+        Window {
+            id: node.id,
+            title: node.name.clone(),
+            app_id: node.app_id.clone(),
+            class: properties.and_then(|properties| properties.class.clone()),
+            instance: properties.and_then(|properties| properties.instance.clone()),
+            shell: match node.shell {
+                Some(ShellType::XdgShell) => Some("xdg_shell"),
+                Some(ShellType::Xwayland) => Some("xwayland"),
+                _ => None,
+            },
+            pid: node.pid,
+            floating: node.node_type == NodeType::FloatingCon,
+            // 1 is fullscreen on the output, 2 is fullscreen across all outputs
+            fullscreen: matches!(node.fullscreen_mode, Some(mode) if mode > 0),
+            sticky: node.sticky,
+            marks: node.marks.clone(),
+            geometry: Geometry {
+                x: node.rect.x,
+                y: node.rect.y,
+                width: node.rect.width,
+                height: node.rect.height,
+            },
+            urgent: node.urgent,
+        }
     }
-    result.push(node.to_owned());
-    result
 }
 
-impl Data {
-    fn get(conn: &mut Connection) -> Result<Self, Box<dyn std::error::Error>> {
-        let workspaces: Vec<Workspace> = conn
-            .get_workspaces()?
+/// A workspace with its windows, for a taskbar
+#[derive(Debug, Serialize)]
+struct TaskbarWorkspace {
+    id: i64,
+    name: Option<String>,
+    output: Option<String>,
+    windows: Vec<TaskbarWindow>,
+}
+
+#[derive(Debug, Serialize)]
+struct TaskbarWindow {
+    id: i64,
+    /// The X11 class for Xwayland windows, since they don't have an app id
+    app_id: Option<String>,
+    title: Option<String>,
+    focused: bool,
+    urgent: bool,
+    floating: bool,
+}
+
+impl TaskbarWindow {
+    fn new(node: &sway::Node) -> Self {
+        TaskbarWindow {
+            id: node.id,
+            app_id: node.app_id.clone().or_else(|| {
+                node.window_properties
+                    .as_ref()
+                    .and_then(|properties| properties.class.clone())
+            }),
+            title: node.name.clone(),
+            focused: node.focused,
+            urgent: node.urgent,
+            floating: node.node_type == NodeType::FloatingCon,
+        }
+    }
+}
+
+/// Collects the windows in a node, windows are the leaves of the tree, everything in between
+/// is a split container
+fn collect_windows<'a>(node: &'a sway::Node, windows: &mut Vec<&'a sway::Node>) {
+    if is_window(node) && node.nodes.is_empty() && node.floating_nodes.is_empty() {
+        windows.push(node);
+    }
+    for child in node.nodes.iter().chain(&node.floating_nodes) {
+        collect_windows(child, windows);
+    }
+}
+
+/// The windows hidden in the scratchpad
+#[derive(Debug, Serialize)]
+struct Scratchpad {
+    count: usize,
+    titles: Vec<String>,
+}
+
+/// A monitor, with the workspace and window that have focus on it, every output has its own
+#[derive(Debug, Serialize)]
+struct Output {
+    name: String,
+    make: String,
+    model: String,
+    /// Disabled outputs are still listed, but don't have a mode or workspace
+    active: bool,
+    /// Whether the output is turned on, it's turned off e.g. by swayidle
+    power: bool,
+    focused: bool,
+    current_mode: Option<OutputMode>,
+    scale: Option<f64>,
+    transform: Option<String>,
+    x: i32,
+    y: i32,
+    workspace: Option<String>,
+    window_title: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct OutputMode {
+    width: i32,
+    height: i32,
+    /// In mHz, e.g. 60000 for 60Hz
+    refresh: i32,
+}
+
+impl Output {
+    /// The workspace and window come from the tree, so they're up to date even if the output
+    /// itself hasn't changed
+    fn new(output: &sway::Output, tree: &sway::Node) -> Self {
+        let workspace = tree
+            .nodes
+            .iter()
+            .find(|node| node.name.as_deref() == Some(output.name.as_str()))
+            .and_then(focused_child);
+        Output {
+            name: output.name.clone(),
+            make: output.make.clone(),
+            model: output.model.clone(),
+            active: output.active,
+            power: output.power,
+            focused: output.focused,
+            current_mode: output.current_mode.as_ref().map(|mode| OutputMode {
+                width: mode.width,
+                height: mode.height,
+                refresh: mode.refresh,
+            }),
+            scale: output.scale,
+            transform: output.transform.clone(),
+            x: output.rect.x,
+            y: output.rect.y,
+            workspace: workspace.and_then(|workspace| workspace.name.clone()),
+            window_title: workspace
+                .and_then(focused_window)
+                .and_then(|window| window.name.clone()),
+        }
+    }
+}
+
+/// The child of a node that has (or last had) focus, the first id in `focus` is the most recent
+fn focused_child(node: &sway::Node) -> Option<&sway::Node> {
+    let id = node.focus.first()?;
+    node.nodes
+        .iter()
+        .chain(&node.floating_nodes)
+        .find(|child| child.id == *id)
+}
+
+/// Follows the focus down to the window that has focus within a workspace, even if the
+/// workspace itself isn't focused
+fn focused_window(workspace: &sway::Node) -> Option<&sway::Node> {
+    let mut node = workspace;
+    while let Some(child) = focused_child(node) {
+        node = child;
+    }
+    is_window(node).then_some(node)
+}
+
+fn is_window(node: &sway::Node) -> bool {
+    matches!(node.node_type, NodeType::Con | NodeType::FloatingCon)
+}
+
+/// The node that has focus right now, there's only ever one of them
+fn find_focused(node: &sway::Node) -> Option<&sway::Node> {
+    if node.focused {
+        return Some(node);
+    }
+    node.nodes
+        .iter()
+        .chain(&node.floating_nodes)
+        .find_map(find_focused)
+}
+
+type Error = Box<dyn std::error::Error>;
+
+fn get_workspaces(conn: &mut Connection) -> Result<Vec<Workspace>, Error> {
+    Ok(conn
+        .get_workspaces()?
+        .into_iter()
+        .map(|workspace| Workspace {
+            id: workspace.id,
+            name: workspace.name,
+            visible: workspace.visible,
+            focused: workspace.focused,
+            urgent: workspace.urgent,
+            output: workspace.output,
+        })
+        .collect())
+}
+
+fn kbd_layout(inputs: &[sway::Input]) -> Option<String> {
+    let mut layout = None;
+    for l in inputs {
+        if let Some(layout_name) = &l.xkb_active_layout_name {
+            layout = Some(layout_name.clone());
+        }
+    }
+    layout
+}
+
+fn focused_window_node(tree: &sway::Node) -> Option<&sway::Node> {
+    find_focused(tree).filter(|node| is_window(node))
+}
+
+fn window(tree: &sway::Node) -> Option<Window> {
+    focused_window_node(tree).map(Window::from)
+}
+
+/// The scratchpad is a workspace called __i3_scratch on an output called __i3
+fn scratchpad(tree: &sway::Node) -> Scratchpad {
+    let mut windows = Vec::new();
+    if let Some(workspace) = tree
+        .nodes
+        .iter()
+        .flat_map(|output| &output.nodes)
+        .find(|node| node.name.as_deref() == Some("__i3_scratch"))
+    {
+        collect_windows(workspace, &mut windows);
+    }
+    Scratchpad {
+        count: windows.len(),
+        titles: windows
             .into_iter()
-            .map(|workspace| Workspace {
-                id: workspace.id,
-                name: workspace.name,
-                visible: workspace.visible,
-                focused: workspace.focused,
-                urgent: workspace.urgent,
-                output: workspace.output,
+            .map(|window| window.name.clone().unwrap_or_default())
+            .collect(),
+    }
+}
+
+fn taskbar(tree: &sway::Node) -> Vec<TaskbarWorkspace> {
+    tree.nodes
+        .iter()
+        .filter(|node| node.node_type == NodeType::Output)
+        .filter(|node| node.name.as_deref() != Some("__i3"))
+        .flat_map(|output| {
+            output
+                .nodes
+                .iter()
+                .filter(|node| node.node_type == NodeType::Workspace)
+                .map(|workspace| {
+                    let mut windows = Vec::new();
+                    collect_windows(workspace, &mut windows);
+                    TaskbarWorkspace {
+                        id: workspace.id,
+                        name: workspace.name.clone(),
+                        output: output.name.clone(),
+                        windows: windows.into_iter().map(TaskbarWindow::new).collect(),
+                    }
+                })
+        })
+        .collect()
+}
+
+/// Returns the cached value, querying sway for it if it isn't known
+fn cached<T>(cache: &mut Option<T>, query: impl FnOnce() -> Result<T, Error>) -> Result<&T, Error> {
+    Ok(match cache.take() {
+        Some(value) => cache.insert(value),
+        None => cache.insert(query()?),
+    })
+}
+
+fn find_node(node: &sway::Node, id: i64) -> Option<&sway::Node> {
+    if node.id == id {
+        return Some(node);
+    }
+    node.nodes
+        .iter()
+        .chain(&node.floating_nodes)
+        .find_map(|child| find_node(child, id))
+}
+
+/// Marks the node with the given id as the only focused one and moves it to the front of the
+/// focus lists above it, returns whether it was found
+fn set_focus(node: &mut sway::Node, id: i64) -> bool {
+    node.focused = node.id == id;
+    let mut found = None;
+    for child in node.nodes.iter_mut().chain(node.floating_nodes.iter_mut()) {
+        if set_focus(child, id) {
+            found = Some(child.id);
+        }
+    }
+    if let Some(child) = found {
+        node.focus.retain(|focused| *focused != child);
+        node.focus.insert(0, child);
+    }
+    node.focused || found.is_some()
+}
+
+/// Swaps the node with the same id as the given one for it, returns whether it was found
+fn replace(node: &mut sway::Node, new: &sway::Node) -> bool {
+    if node.id == new.id {
+        *node = new.clone();
+        return true;
+    }
+    node.nodes
+        .iter_mut()
+        .chain(node.floating_nodes.iter_mut())
+        .any(|child| replace(child, new))
+}
+
+/// Everything that was queried from sway so far, kept up to date from the event payloads where
+/// possible, parts that are None are queried again the next time they're needed
+#[derive(Default)]
+struct Model {
+    // The tree has the root node at the top, which contains the outputs, which contain the
+    // workspaces, which contain the windows (possibly nested in split containers)
+    tree: Option<sway::Node>,
+    workspaces: Option<Vec<Workspace>>,
+    inputs: Option<Vec<sway::Input>>,
+    outputs: Option<Vec<sway::Output>>,
+    binding_modes: Option<Vec<String>>,
+    binding_state: Option<String>,
+    /// This doesn't come from sway, but it's only read once
+    layout_codes: Option<HashMap<String, String>>,
+    /// The `pango_markup` flag of the last mode event
+    pango_markup: bool,
+}
+
+impl Model {
+    fn tree(&mut self, conn: &mut Connection) -> Result<&sway::Node, Error> {
+        cached(&mut self.tree, || Ok(conn.get_tree()?))
+    }
+
+    fn workspaces(&mut self, conn: &mut Connection) -> Result<&Vec<Workspace>, Error> {
+        cached(&mut self.workspaces, || get_workspaces(conn))
+    }
+
+    fn kbd_layout(&mut self, conn: &mut Connection) -> Result<Option<String>, Error> {
+        let inputs = cached(&mut self.inputs, || Ok(conn.get_inputs()?))?;
+        Ok(kbd_layout(inputs))
+    }
+
+    fn keyboards(&mut self, conn: &mut Connection) -> Result<Vec<Keyboard>, Error> {
+        let inputs = cached(&mut self.inputs, || Ok(conn.get_inputs()?))?;
+        let codes = cached(&mut self.layout_codes, || Ok(read_layout_codes()))?;
+        Ok(inputs
+            .iter()
+            .filter(|input| input.input_type == "keyboard")
+            .map(|input| Keyboard::new(input, codes))
+            .collect())
+    }
+
+    fn outputs(&mut self, conn: &mut Connection) -> Result<Vec<Output>, Error> {
+        let outputs = cached(&mut self.outputs, || Ok(conn.get_outputs()?))?;
+        let tree = cached(&mut self.tree, || Ok(conn.get_tree()?))?;
+        Ok(outputs
+            .iter()
+            .map(|output| Output::new(output, tree))
+            .collect())
+    }
+
+    fn binding_modes(&mut self, conn: &mut Connection) -> Result<Vec<BindingMode>, Error> {
+        let modes = cached(&mut self.binding_modes, || Ok(conn.get_binding_modes()?))?;
+        let current = cached(&mut self.binding_state, || Ok(conn.get_binding_state()?))?;
+        Ok(modes
+            .iter()
+            .map(|mode| {
+                let active = mode == current;
+                BindingMode {
+                    name: mode.clone(),
+                    active,
+                    pango_markup: active && self.pango_markup,
+                }
             })
-            .collect();
-        let inputs = conn.get_inputs()?;
-        let mut layout = None;
-        for l in inputs {
-            if let Some(layout_name) = l.xkb_active_layout_name {
-                layout = Some(layout_name);
+            .collect())
+    }
+
+    /// Updates the model from an event, anything that can't be updated from the payload is
+    /// queried again later
+    fn apply(&mut self, event: &Event) {
+        match event {
+            Event::Workspace(event) => self.apply_workspace(event),
+            Event::Window(event) => self.apply_window(event),
+            Event::Input(event) => self.apply_input(event),
+            Event::Mode(mode) => {
+                self.binding_state = Some(mode.change.clone());
+                self.pango_markup = mode.pango_markup;
+            }
+            Event::Output(_) => {
+                self.outputs = None;
+                self.tree = None;
+                self.workspaces = None;
             }
+            _ => {}
         }
-        let current_binding_mode = conn.get_binding_state()?;
-        let binding_modes = conn
-            .get_binding_modes()?
-            .into_iter()
-            .map(|mode| BindingMode {
-                name: mode.clone(),
-                active: mode == current_binding_mode,
+    }
+
+    fn apply_workspace(&mut self, event: &WorkspaceEvent) {
+        let Some(current) = &event.current else {
+            self.tree = None;
+            self.workspaces = None;
+            return;
+        };
+        if let WorkspaceChange::Focus = event.change {
+            self.focus_output(current.id);
+        }
+        // Focusing a workspace focuses the window that last had focus on it
+        let focused = match (&event.change, &mut self.tree) {
+            (WorkspaceChange::Focus, Some(tree)) => {
+                let target = find_node(tree, current.id).map(|workspace| {
+                    focused_window(workspace).map_or(workspace.id, |window| window.id)
+                });
+                matches!(target, Some(target) if set_focus(tree, target))
+            }
+            _ => false,
+        };
+        if !focused {
+            self.tree = None;
+        }
+        let Some(workspaces) = &mut self.workspaces else {
+            return;
+        };
+        let output = workspaces
+            .iter()
+            .find(|workspace| workspace.id == current.id)
+            .map(|workspace| workspace.output.clone());
+        match (&event.change, output) {
+            (WorkspaceChange::Focus, Some(output)) => {
+                for workspace in workspaces.iter_mut() {
+                    workspace.focused = workspace.id == current.id;
+                    if workspace.output == output {
+                        workspace.visible = workspace.id == current.id;
+                    }
+                }
+            }
+            (WorkspaceChange::Urgent | WorkspaceChange::Rename, Some(_)) => {
+                for workspace in workspaces.iter_mut() {
+                    if workspace.id == current.id {
+                        workspace.urgent = current.urgent;
+                        workspace.name = current.name.clone().unwrap_or_default();
+                    }
+                }
+            }
+            // Workspaces being created, destroyed or moved change their surroundings as well
+            _ => self.workspaces = None,
+        }
+    }
+
+    /// Moves the focus of the outputs to the one the given workspace is on
+    fn focus_output(&mut self, workspace: i64) {
+        let output = self
+            .tree
+            .as_ref()
+            .and_then(|tree| {
+                tree.nodes
+                    .iter()
+                    .find(|output| output.nodes.iter().any(|node| node.id == workspace))
             })
-            .collect();
-        // TODO: Make this more robust
-        // Currently, we go through all nodes that exist and find a focused on, this should work
-        // reliably on a single monitor setup, but I believe it's going to fail when multiple
-        // monitors are involved.
-        // When we call get_tree(), the data is structured as follows:
-        // We get the root node, this should be the only node at this level
-        // The root node contains monitor nodes
-        // The monitor nodes contain workspace nodes, corresponding to the workspaces on that
-        // monitor
-        // The monitor nodes contain Con nodes, which are what we're after.
-        // To make this work for multi-monitor, we'd have to extract the focused node for each
-        // monitor and then output the active window name for each monitor seperately.
-        let mut root_node = conn.get_tree().unwrap();
-        let window_title = if let Some(current_window) = flatten_nodes(&mut root_node)
-            .into_iter()
-            .filter(|node| node.node_type == NodeType::Con && node.focused)
-            .collect::<Vec<sway::Node>>()
-            .first()
-        {
-            current_window.name.clone()
-        } else {
-            None
+            .and_then(|output| output.name.clone())
+            .or_else(|| {
+                self.workspaces
+                    .as_ref()?
+                    .iter()
+                    .find(|node| node.id == workspace)
+                    .map(|node| node.output.clone())
+            });
+        match (output, &mut self.outputs) {
+            (Some(name), Some(outputs)) => {
+                for output in outputs.iter_mut() {
+                    output.focused = output.name == name;
+                }
+            }
+            _ => self.outputs = None,
+        }
+    }
+
+    fn apply_window(&mut self, event: &WindowEvent) {
+        let Some(tree) = &mut self.tree else {
+            return;
+        };
+        let updated = match event.change {
+            WindowChange::Focus => {
+                replace(tree, &event.container) && set_focus(tree, event.container.id)
+            }
+            WindowChange::Title
+            | WindowChange::Urgent
+            | WindowChange::Mark
+            | WindowChange::FullscreenMode => replace(tree, &event.container),
+            // Windows being opened, closed or moved change their surroundings as well
+            _ => false,
         };
+        if !updated {
+            self.tree = None;
+        }
+    }
 
+    fn apply_input(&mut self, event: &InputEvent) {
+        let Some(inputs) = &mut self.inputs else {
+            return;
+        };
+        let identifier = &event.input.identifier;
+        match event.change {
+            InputChange::Removed => inputs.retain(|input| &input.identifier != identifier),
+            _ => match inputs
+                .iter_mut()
+                .find(|input| &input.identifier == identifier)
+            {
+                Some(input) => *input = event.input.clone(),
+                None => inputs.push(event.input.clone()),
+            },
+        }
+    }
+}
+
+impl Data {
+    fn get(model: &mut Model, conn: &mut Connection) -> Result<Self, Error> {
+        let kbd_layout = model.kbd_layout(conn)?;
+        let keyboards = model.keyboards(conn)?;
+        let workspaces = model.workspaces(conn)?.clone();
+        let binding_modes = model.binding_modes(conn)?;
+        // i3 leaves out fields swayipc requires for outputs, the rest still works there
+        let outputs = model.outputs(conn).unwrap_or_default();
+        let tree = model.tree(conn)?;
         Ok(Self {
-            kbd_layout: layout,
-            window_title,
+            kbd_layout,
+            keyboards,
+            window_title: focused_window_node(tree).and_then(|node| node.name.clone()),
+            window: window(tree),
             workspaces,
             binding_modes,
+            outputs,
+            scratchpad: scratchpad(tree),
         })
     }
 }
 
-pub struct Sway {}
+fn print_result<T: Serialize>(data: Result<T, Error>) {
+    match data {
+        Ok(data) => crate::print(&Some(data)),
+        Err(_) => crate::print::<T>(&None),
+    }
+}
+
+#[derive(Args)]
+pub struct SwayOpts {
+    /// Only print one part of the data, everything is printed by default
+    #[command(subcommand)]
+    mode: Option<SwayMode>,
+}
+
+#[derive(Subcommand, Clone, Copy)]
+pub enum SwayMode {
+    /// Every workspace
+    Workspace,
+    /// The focused window with everything sway knows about it
+    Window,
+    /// Every keyboard with its layouts
+    Keyboard,
+    /// Every binding mode, e.g. "resize"
+    Mode,
+    /// Every output with its focused workspace and window
+    Outputs,
+    /// Every workspace with its windows
+    Taskbar,
+    /// How many windows are in the scratchpad, and their titles
+    Scratchpad,
+}
+
+impl SwayMode {
+    /// The events that can change this part of the data
+    fn events(self) -> Vec<EventType> {
+        match self {
+            SwayMode::Workspace => vec![EventType::Workspace],
+            // Switching to an empty workspace unfocuses the window without a window event
+            SwayMode::Window => vec![EventType::Window, EventType::Workspace],
+            SwayMode::Keyboard => vec![EventType::Input],
+            SwayMode::Mode => vec![EventType::Mode],
+            SwayMode::Outputs => vec![EventType::Workspace, EventType::Window, EventType::Output],
+            SwayMode::Taskbar => vec![EventType::Workspace, EventType::Window],
+            SwayMode::Scratchpad => vec![EventType::Window],
+        }
+    }
+
+    /// Whether an event can change this part of the data, for the ones where not every event of
+    /// a type does
+    fn is_relevant(self, event: &Event) -> bool {
+        match (self, event) {
+            // Windows are moved to and from the scratchpad
+            (SwayMode::Scratchpad, Event::Window(window)) => matches!(
+                window.change,
+                WindowChange::Move | WindowChange::Close | WindowChange::Title
+            ),
+            _ => true,
+        }
+    }
+}
+
+#[derive(Args)]
+pub struct SwayCtl {
+    #[command(subcommand)]
+    command: SwayCommand,
+}
+
+#[derive(Subcommand)]
+enum SwayCommand {
+    /// Focus a window by the id of its container, e.g. from the taskbar mode
+    Focus { id: i64 },
+}
+
+pub fn control(ctl: &SwayCtl) -> Result<()> {
+    let mut conn = Connection::new()?;
+    match ctl.command {
+        SwayCommand::Focus { id } => {
+            for outcome in conn.run_command(format!("[con_id={id}] focus"))? {
+                outcome?;
+            }
+        }
+    }
+    Ok(())
+}
+
+pub struct Sway {
+    mode: Option<SwayMode>,
+    model: RefCell<Model>,
+}
+
+impl Sway {
+    pub fn new(opts: &SwayOpts) -> Self {
+        Self {
+            mode: opts.mode,
+            model: RefCell::new(Model::default()),
+        }
+    }
+}
 
 impl Module for Sway {
     type Connection = Connection;
@@ -118,21 +790,43 @@ impl Module for Sway {
     }
 
     fn output(&self, conn: &mut Self::Connection) {
-        let data = Data::get(conn);
-        match data {
-            Ok(data) => crate::print(&Some(&data)),
-            Err(_) => crate::print::<Data>(&None),
+        let mut model = self.model.borrow_mut();
+        match self.mode {
+            None => print_result(Data::get(&mut model, conn)),
+            Some(SwayMode::Workspace) => print_result(model.workspaces(conn)),
+            Some(SwayMode::Window) => print_result(model.tree(conn).map(window)),
+            Some(SwayMode::Keyboard) => print_result(model.keyboards(conn)),
+            Some(SwayMode::Mode) => print_result(model.binding_modes(conn)),
+            Some(SwayMode::Outputs) => print_result(model.outputs(conn)),
+            Some(SwayMode::Taskbar) => print_result(model.tree(conn).map(taskbar)),
+            Some(SwayMode::Scratchpad) => print_result(model.tree(conn).map(scratchpad)),
         }
     }
 
     fn start(&mut self, timeout: u64) -> Result<()> {
         let mut conn = self.connect(timeout)?;
         self.output(&mut conn);
-        for _ in Connection::new()?.subscribe([
-            EventType::Input,
-            EventType::Workspace,
-            EventType::Window,
-        ])? {
+        let events = match self.mode {
+            Some(mode) => mode.events(),
+            None => vec![
+                EventType::Input,
+                EventType::Workspace,
+                EventType::Window,
+                EventType::Mode,
+                EventType::Output,
+            ],
+        };
+        for event in Connection::new()?.subscribe(events)? {
+            match &event {
+                Ok(event) => self.model.get_mut().apply(event),
+                // Without the payload, nothing about the model can be trusted anymore
+                Err(_) => *self.model.get_mut() = Model::default(),
+            }
+            if let (Some(mode), Ok(event)) = (self.mode, &event) {
+                if !mode.is_relevant(event) {
+                    continue;
+                }
+            }
             self.output(&mut conn);
         }
         Ok(())